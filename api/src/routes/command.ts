@@ -4,8 +4,9 @@ import { PublicKey, Keypair } from '@solana/web3.js';
 import { getRobyClient } from '../config';
 import { validate } from '../middleware/validation';
 import { strictLimiter } from '../middleware/rateLimit';
+import { createError } from '../middleware/errorHandler';
 import { logger } from '../utils/logger';
-import { CommandType } from '@roby/sdk';
+import { CommandType, EMPTY_SPARSE_PROOF } from '@roby/sdk';
 import bs58 from 'bs58';
 
 export const commandRoutes = Router();
@@ -20,6 +21,9 @@ commandRoutes.post(
   body('commandType').isInt({ min: 0, max: 8 }),
   body('parameters').isString(),
   body('merkleProof').isArray(),
+  body('leafIndex').isInt({ min: 0 }),
+  body('revocationBitmap').optional().isString(),
+  body('revocationSiblings').optional().isArray(),
   validate([
     body('robotPublicKey'),
     body('credentialPublicKey'),
@@ -27,6 +31,7 @@ commandRoutes.post(
     body('commandType'),
     body('parameters'),
     body('merkleProof'),
+    body('leafIndex'),
   ]),
   async (req: Request, res: Response, next: NextFunction) => {
     try {
@@ -42,6 +47,19 @@ commandRoutes.post(
       const merkleProof = req.body.merkleProof.map((p: string) =>
         Buffer.from(p, 'hex')
       );
+      const revocationProof = req.body.revocationBitmap
+        ? {
+            siblingBitmap: Buffer.from(req.body.revocationBitmap, 'hex'),
+            siblings: (req.body.revocationSiblings ?? []).map((s: string) =>
+              Buffer.from(s, 'hex')
+            ),
+          }
+        : EMPTY_SPARSE_PROOF;
+      
+      const robotData = await client.getRobotData(robot);
+      if (!robotData) {
+        throw createError('Robot not found', 404, 'ROBOT_NOT_FOUND');
+      }
       
       const result = await client.executeCommand(
         {
@@ -50,7 +68,12 @@ commandRoutes.post(
           credential,
           commandType,
           parameters,
-          merkleProof,
+          proof: {
+            merkleProof,
+            leafIndex: req.body.leafIndex,
+            revocationProof,
+          },
+          expectedSequence: robotData.commandSequence,
         },
         executorKeypair
       );
@@ -58,12 +81,14 @@ commandRoutes.post(
       logger.info('Command executed:', {
         robot: robot.toBase58(),
         commandType,
+        sequence: robotData.commandSequence,
         commandLog: result.commandLogAccount.toBase58(),
         signature: result.signature,
       });
       
       res.status(200).json({
         commandLogAccount: result.commandLogAccount.toBase58(),
+        sequence: robotData.commandSequence,
         signature: result.signature,
       });
     } catch (error) {
@@ -32,14 +32,18 @@ robotRoutes.get(
         data: {
           owner: robotData.owner.toBase58(),
           authority: robotData.authority.toBase58(),
+          deviceKey: robotData.deviceKey.toBase58(),
           status: robotData.status,
           robotId: robotData.robotId.toString('hex'),
           merkleRoot: robotData.merkleRoot.toString('hex'),
           lastCommandTimestamp: robotData.lastCommandTimestamp,
           totalCommandsExecuted: robotData.totalCommandsExecuted,
+          commandSequence: robotData.commandSequence,
+          lastHeartbeat: robotData.lastHeartbeat,
           activeOperators: robotData.activeOperators.map(op => op.toBase58()),
           maxOperators: robotData.maxOperators,
           emergencyStop: robotData.emergencyStop,
+          emergencyStopReason: robotData.emergencyStopReason,
           metadataUri: robotData.metadataUri,
         },
       });
@@ -56,12 +60,14 @@ robotRoutes.post(
   body('metadataUri').isString(),
   body('ownerPrivateKey').isString(),
   body('authorityPublicKey').isString(),
+  body('deviceKeyPublicKey').isString(),
   validate([
     body('robotId'),
     body('merkleRoot'),
     body('metadataUri'),
     body('ownerPrivateKey'),
     body('authorityPublicKey'),
+    body('deviceKeyPublicKey'),
   ]),
   async (req: Request, res: Response, next: NextFunction) => {
     try {
@@ -75,6 +81,7 @@ robotRoutes.post(
         bs58.decode(req.body.ownerPrivateKey)
       );
       const authority = new PublicKey(req.body.authorityPublicKey);
+      const deviceKey = new PublicKey(req.body.deviceKeyPublicKey);
       
       const result = await client.initializeRobot(
         {
@@ -83,6 +90,7 @@ robotRoutes.post(
           metadataUri,
           owner: ownerKeypair.publicKey,
           authority,
+          deviceKey,
         },
         ownerKeypair
       );
@@ -106,6 +114,7 @@ robotRoutes.post(
   '/:publicKey/emergency-stop',
   param('publicKey').isString(),
   body('authorityPrivateKey').isString(),
+  body('reasonCode').optional().isInt({ min: 0, max: 65535 }),
   validate([param('publicKey'), body('authorityPrivateKey')]),
   async (req: Request, res: Response, next: NextFunction) => {
     try {
@@ -114,14 +123,17 @@ robotRoutes.post(
       const authorityKeypair = Keypair.fromSecretKey(
         bs58.decode(req.body.authorityPrivateKey)
       );
+      const reasonCode = req.body.reasonCode ?? 0;
       
       const signature = await client.emergencyStop(
         robotPubkey,
-        authorityKeypair
+        authorityKeypair,
+        reasonCode
       );
       
       logger.warn('Emergency stop activated:', {
         robot: robotPubkey.toBase58(),
+        reasonCode,
         signature,
       });
       
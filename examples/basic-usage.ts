@@ -1,5 +1,15 @@
-import { RobyClient, MerkleTree, createCredentialLeaf, PermissionLevel, CommandType } from '@roby/sdk';
+import {
+  RobyClient,
+  MerkleTree,
+  createCredentialLeaf,
+  deriveRobotAddress,
+  generateRobotId,
+  PermissionLevel,
+  CommandType,
+  EMPTY_SPARSE_PROOF,
+} from '@roby/sdk';
 import { Connection, Keypair, PublicKey } from '@solana/web3.js';
+import { randomBytes } from 'crypto';
 
 async function main() {
   const connection = new Connection('https://api.devnet.solana.com', 'confirmed');
@@ -9,21 +19,32 @@ async function main() {
   const owner = Keypair.generate();
   const authority = Keypair.generate();
   const operator = Keypair.generate();
+  const device = Keypair.generate();
 
   console.log('Airdropping SOL for testing...');
-  const airdropSignature = await connection.requestAirdrop(
-    owner.publicKey,
-    2000000000
-  );
-  await connection.confirmTransaction(airdropSignature);
+  for (const payer of [owner, operator]) {
+    const airdropSignature = await connection.requestAirdrop(
+      payer.publicKey,
+      2000000000
+    );
+    await connection.confirmTransaction(airdropSignature);
+  }
+
+  const robotId = generateRobotId();
+  const [robotAddress] = deriveRobotAddress(programId, owner.publicKey, robotId);
+
+  const validFrom = Math.floor(Date.now() / 1000);
+  const validUntil = validFrom + 86400;
+  const credentialHash = randomBytes(32);
 
   const credentials = [
     createCredentialLeaf(
-      operator.publicKey.toBase58(),
-      '',
+      operator.publicKey,
+      robotAddress,
       PermissionLevel.Operator,
-      Math.floor(Date.now() / 1000),
-      Math.floor(Date.now() / 1000) + 86400
+      validFrom,
+      validUntil,
+      credentialHash
     ),
   ];
 
@@ -32,42 +53,48 @@ async function main() {
 
   console.log('Initializing robot...');
   const { robotAccount, signature: initSig } = await client.initializeRobot({
-    robotId: Buffer.from('robot-001-test'),
+    robotId,
     merkleRoot,
     metadataUri: 'https://example.com/robot-metadata.json',
     owner: owner.publicKey,
     authority: authority.publicKey,
-  }, owner);
+    deviceKey: device.publicKey,
+  }, owner, authority);
 
   console.log(`Robot initialized: ${robotAccount.toBase58()}`);
   console.log(`Transaction: ${initSig}`);
 
-  const updatedCredentials = credentials.map(c => {
-    const leaf = createCredentialLeaf(
-      operator.publicKey.toBase58(),
-      robotAccount.toBase58(),
-      PermissionLevel.Operator,
-      Math.floor(Date.now() / 1000),
-      Math.floor(Date.now() / 1000) + 86400
-    );
-    return leaf;
-  });
-
   console.log('Issuing credential...');
   const { credentialAccount } = await client.issueCredential({
     recipient: operator.publicKey,
     robot: robotAccount,
     permissionLevel: PermissionLevel.Operator,
-    validFrom: Math.floor(Date.now() / 1000),
-    validUntil: Math.floor(Date.now() / 1000) + 86400,
-    credentialHash: updatedCredentials[0],
+    validFrom,
+    validUntil,
+    credentialHash,
     issuer: authority.publicKey,
-  }, authority);
+  }, owner, authority);
 
   console.log(`Credential issued: ${credentialAccount.toBase58()}`);
 
+  console.log('Executing command...');
   const robotData = await client.getRobotData(robotAccount);
-  console.log('Robot data:', robotData);
+  const { commandLogAccount } = await client.executeCommand({
+    robot: robotAccount,
+    executor: operator.publicKey,
+    credential: credentialAccount,
+    commandType: CommandType.Calibrate,
+    parameters: Buffer.alloc(0),
+    proof: {
+      merkleProof: merkleTree.getProofAt(0),
+      leafIndex: 0,
+      revocationProof: EMPTY_SPARSE_PROOF,
+    },
+    expectedSequence: robotData!.commandSequence,
+  }, operator);
+
+  console.log(`Command logged: ${commandLogAccount.toBase58()}`);
+  console.log('Robot data:', await client.getRobotData(robotAccount));
 
   console.log('Example completed successfully!');
 }
@@ -85,4 +112,3 @@ main().catch(console.error);
 
 
 
-
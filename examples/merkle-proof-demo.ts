@@ -1,10 +1,11 @@
-import { MerkleTree, createCredentialLeaf, PermissionLevel } from '@roby/sdk';
-import { Keypair, PublicKey } from '@solana/web3.js';
+import { MerkleTree, createCredentialLeaf, hashLeaf, PermissionLevel } from '@roby/sdk';
+import { Keypair } from '@solana/web3.js';
+import { randomBytes } from 'crypto';
 
 function demoMerkleTree() {
   console.log('=== Merkle Tree Credential System Demo ===\n');
 
-  const robotPublicKey = 'RobotPublicKeyExample123456789';
+  const robotPublicKey = Keypair.generate().publicKey;
   const validFrom = Math.floor(Date.now() / 1000);
   const validUntil = validFrom + 86400;
 
@@ -19,11 +20,12 @@ function demoMerkleTree() {
 
   const credentials = operators.map((op, i) =>
     createCredentialLeaf(
-      operatorKeys[i].publicKey.toBase58(),
+      operatorKeys[i].publicKey,
       robotPublicKey,
       op.level,
       validFrom,
-      validUntil
+      validUntil,
+      randomBytes(32)
     )
   );
 
@@ -52,7 +54,7 @@ function demoMerkleTree() {
   console.log(`Proof valid: ${isValid}\n`);
 
   console.log('Testing invalid proof:');
-  const fakeCredential = Buffer.from('fake_credential_data');
+  const fakeCredential = hashLeaf(Buffer.from('fake_credential_data'));
   const isInvalid = MerkleTree.verify(fakeCredential, aliceProof, root);
   console.log(`Fake credential valid: ${isInvalid}\n`);
 
@@ -47,6 +47,63 @@ pub enum RobyError {
     
     #[error("Robot Offline")]
     RobotOffline,
+    
+    #[error("Invalid Account Type")]
+    InvalidAccountType,
+    
+    #[error("Account Version Mismatch")]
+    AccountVersionMismatch,
+    
+    #[error("Unsupported Merkle Tree Scheme")]
+    UnsupportedTreeScheme,
+    
+    #[error("Credential Tree Full")]
+    CredentialTreeFull,
+    
+    #[error("Credential Revoked")]
+    CredentialRevoked,
+    
+    #[error("Invalid Command Sequence")]
+    InvalidCommandSequence,
+    
+    #[error("Command Queue Full")]
+    CommandQueueFull,
+    
+    #[error("Invalid Command Status")]
+    InvalidCommandStatus,
+    
+    #[error("Resume Not Approved")]
+    ResumeNotApproved,
+    
+    #[error("Proposal Expired")]
+    ProposalExpired,
+    
+    #[error("Proposal Required")]
+    ProposalRequired,
+    
+    #[error("Proposal Not Executable")]
+    ProposalNotExecutable,
+    
+    #[error("Insufficient Permission Level")]
+    InsufficientPermissionLevel,
+    
+    #[error("Delegation Not Allowed")]
+    DelegationNotAllowed,
+    
+    #[error("Command Not In Scope")]
+    CommandNotInScope,
+    
+    #[error("Parameter Out Of Bounds")]
+    ParameterOutOfBounds,
+    
+    #[error("Outside Time Window")]
+    OutsideTimeWindow,
+    
+    #[error("Dependent Accounts Open")]
+    DependentAccountsOpen,
+    
+    #[error("Resume Policy Locked")]
+    ResumePolicyLocked,
 }
 
 impl From<RobyError> for ProgramError {
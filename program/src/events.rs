@@ -0,0 +1,30 @@
+//! Structured events emitted through `sol_log_data`.
+//!
+//! Each event is logged as two data fields: its name and its Borsh-encoded
+//! body, so indexers can filter on the first field before decoding.
+
+use borsh::BorshSerialize;
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+use crate::state::RobotStatus;
+
+pub trait Event: BorshSerialize {
+    const NAME: &'static str;
+    
+    fn emit(&self) {
+        if let Ok(data) = self.try_to_vec() {
+            sol_log_data(&[Self::NAME.as_bytes(), &data]);
+        }
+    }
+}
+
+#[derive(BorshSerialize, Debug, Clone)]
+pub struct StatusChanged {
+    pub robot: Pubkey,
+    pub old_status: RobotStatus,
+    pub new_status: RobotStatus,
+}
+
+impl Event for StatusChanged {
+    const NAME: &'static str = "StatusChanged";
+}
@@ -5,14 +5,58 @@ use solana_program::{
     clock::UnixTimestamp,
 };
 
-use crate::state::{PermissionLevel, CommandType};
+use crate::{
+    merkle::{
+        MerkleTreeScheme, MerkleMultiProof, PositionalMultiProof, SparseMerkleProof,
+        SparseMerkleMultiProof,
+    },
+    state::{
+        PermissionLevel, CommandType, CommandStatus, AccountType, RobotStatus, OperatorPolicy,
+        CredentialScope,
+    },
+};
+
+/// Proof that a credential's leaf is part of the robot's credential tree
+/// and absent from its revocation tree. `leaf_index` is only read for
+/// positional trees.
+///
+/// Administrative instructions take an optional `admin_proof`: when set,
+/// the signer acts through an Administrator or Owner credential passed
+/// right after the instruction's fixed accounts, instead of as the robot's
+/// authority or owner key.
+///
+/// For a delegated credential the proof is for the root of its delegation
+/// chain, and the chain's parent credentials are passed nearest first
+/// after the instruction's fixed accounts, or right after the credential
+/// account for `admin_proof`. The robot's credential tree may follow the
+/// chain in either case, to prove against its current root.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct CredentialProof {
+    pub merkle_proof: Vec<[u8; 32]>,
+    pub leaf_index: u32,
+    pub revocation_proof: SparseMerkleProof,
+}
+
+/// Batched proof for `VerifyCredentials`, in the scheme of the root it is
+/// checked against.
+///
+/// `Positional` proofs are checked against the robot's positional roots and
+/// the recent roots of its on-chain credential tree, whose address must be
+/// passed right after the clock even when the tree does not exist.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub enum CredentialMultiProof {
+    SortedPair(MerkleMultiProof),
+    Positional(PositionalMultiProof),
+}
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub enum RobyInstruction {
     InitializeRobot {
         robot_id: [u8; 32],
         merkle_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
         metadata_uri: String,
+        device_key: Pubkey,
     },
     
     IssueCredential {
@@ -20,18 +64,25 @@ pub enum RobyInstruction {
         valid_from: UnixTimestamp,
         valid_until: UnixTimestamp,
         credential_hash: [u8; 32],
+        scope: CredentialScope,
+        append_to_tree: bool,
+        admin_proof: Option<CredentialProof>,
     },
     
-    RevokeCredential,
+    RevokeCredential {
+        admin_proof: Option<CredentialProof>,
+    },
     
     ExecuteCommand {
         command_type: CommandType,
         parameters: Vec<u8>,
-        merkle_proof: Vec<[u8; 32]>,
+        proof: CredentialProof,
+        expected_sequence: u64,
     },
     
     UpdateMerkleRoot {
         new_merkle_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
     },
     
     TransferAuthority {
@@ -40,23 +91,148 @@ pub enum RobyInstruction {
     
     AddOperator {
         operator: Pubkey,
+        admin_proof: Option<CredentialProof>,
     },
     
     RemoveOperator {
         operator: Pubkey,
+        admin_proof: Option<CredentialProof>,
     },
     
-    EmergencyStop,
+    EmergencyStop {
+        reason_code: u16,
+    },
     
     Resume,
     
     UpdateRobotStatus {
         status: u8,
+        admin_proof: Option<CredentialProof>,
     },
     
     TransferOwnership {
         new_owner: Pubkey,
     },
+    
+    CloseRobot,
+    
+    CloseCredential,
+    
+    CloseCommandLog,
+    
+    MigrateAccount {
+        account_type: AccountType,
+    },
+    
+    VerifyCredentials {
+        multiproof: CredentialMultiProof,
+        revocation_proof: SparseMerkleMultiProof,
+    },
+    
+    InitializeCredentialTree {
+        depth: u8,
+    },
+    
+    UpdateRevocationRoot {
+        new_revocation_root: [u8; 32],
+    },
+    
+    SetRootGracePeriod {
+        grace_period: i64,
+    },
+    
+    InitializeCommandQueue,
+    
+    AcknowledgeCommand {
+        sequence: u64,
+        status: CommandStatus,
+        error_code: u32,
+    },
+    
+    RotateDeviceKey {
+        new_device_key: Pubkey,
+        co_signed: bool,
+    },
+    
+    ReportStatus {
+        status: RobotStatus,
+    },
+    
+    Heartbeat {
+        battery_level: Option<u8>,
+        health_flags: Option<u32>,
+    },
+    
+    SetLivenessTimeout {
+        liveness_timeout: i64,
+    },
+    
+    AddGuardian {
+        guardian: Pubkey,
+    },
+    
+    RemoveGuardian {
+        guardian: Pubkey,
+    },
+    
+    SetResumePolicy {
+        resume_threshold: u8,
+        proposal_ttl: i64,
+    },
+    
+    ProposeResume,
+    
+    ApproveResume,
+    
+    InitializeMultisig {
+        multisig_id: [u8; 32],
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    },
+    
+    ConfigureProposals {
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+        timelock: i64,
+    },
+    
+    CreateProposal {
+        instruction_data: Vec<u8>,
+    },
+    
+    ApproveProposal,
+    
+    CancelProposal,
+    
+    ExecuteProposal,
+    
+    AcceptOwnership,
+    
+    AcceptAuthority,
+    
+    CancelTransfer,
+    
+    SetOperatorPolicy {
+        policy: OperatorPolicy,
+    },
+    
+    SetCommandPermission {
+        command_type: CommandType,
+        min_level: PermissionLevel,
+    },
+    
+    DelegateCredential {
+        permission_level: PermissionLevel,
+        valid_from: UnixTimestamp,
+        valid_until: UnixTimestamp,
+        credential_hash: [u8; 32],
+        scope: CredentialScope,
+    },
+    
+    FailCommand {
+        sequence: u64,
+        error_code: u32,
+    },
 }
 
 impl RobyInstruction {
@@ -67,6 +243,18 @@ impl RobyInstruction {
     pub fn pack(&self) -> Vec<u8> {
         self.try_to_vec().expect("pack")
     }
+    
+    /// Changes that must go through `ExecuteProposal` once a robot has
+    /// proposals configured. Each takes the robot as its first account.
+    pub fn requires_proposal(&self) -> bool {
+        matches!(
+            self,
+            RobyInstruction::TransferOwnership { .. }
+                | RobyInstruction::TransferAuthority { .. }
+                | RobyInstruction::UpdateMerkleRoot { .. }
+                | RobyInstruction::ConfigureProposals { .. }
+        )
+    }
 }
 
 
@@ -0,0 +1,218 @@
+//! Account layouts written before accounts carried a discriminator and
+//! layout version. Only `MigrateAccount` reads these.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    error::RobyError,
+    merkle::{MerkleTreeScheme, EMPTY_LEAF},
+    state::{
+        CommandLog, CommandStatus, CommandType, Credential, CredentialScope, OperatorPolicy,
+        PermissionLevel, Robot, RobotStatus, RobyAccount,
+    },
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyRobot {
+    pub is_initialized: bool,
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub status: RobotStatus,
+    pub robot_id: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub last_command_timestamp: UnixTimestamp,
+    pub total_commands_executed: u64,
+    pub active_operators: Vec<Pubkey>,
+    pub max_operators: u8,
+    pub emergency_stop: bool,
+    pub metadata_uri: String,
+}
+
+impl LegacyRobot {
+    pub const LEN: usize = 1 + 32 + 32 + 1 + 32 + 32 + 8 + 8 + (32 * 10) + 1 + 1 + 256;
+}
+
+impl From<LegacyRobot> for Robot {
+    fn from(legacy: LegacyRobot) -> Self {
+        Self {
+            discriminator: Robot::DISCRIMINATOR,
+            version: Robot::VERSION,
+            owner: legacy.owner,
+            authority: legacy.authority,
+            pending_owner: None,
+            pending_authority: None,
+            device_key: Pubkey::default(),
+            status: legacy.status,
+            robot_id: legacy.robot_id,
+            merkle_root: legacy.merkle_root,
+            tree_scheme: MerkleTreeScheme::SortedPair,
+            revocation_root: EMPTY_LEAF,
+            recent_roots: Vec::new(),
+            root_grace_period: Robot::DEFAULT_ROOT_GRACE_PERIOD,
+            last_command_timestamp: legacy.last_command_timestamp,
+            total_commands_executed: legacy.total_commands_executed,
+            command_sequence: 0,
+            last_heartbeat: 0,
+            battery_level: None,
+            health_flags: None,
+            liveness_timeout: 0,
+            active_operators: legacy.active_operators,
+            max_operators: legacy.max_operators,
+            operator_policy: OperatorPolicy::CredentialOnly,
+            command_permissions: [PermissionLevel::Operator; CommandType::COUNT],
+            guardians: Vec::new(),
+            emergency_stop: legacy.emergency_stop,
+            emergency_stop_reason: 0,
+            emergency_stopped_by: Pubkey::default(),
+            emergency_stopped_at: 0,
+            resume_threshold: 0,
+            resume_proposal_ttl: Robot::DEFAULT_RESUME_PROPOSAL_TTL,
+            proposal_approvers: Vec::new(),
+            proposal_threshold: 0,
+            proposal_timelock: 0,
+            proposal_count: 0,
+            open_credentials: 0,
+            open_proposals: 0,
+            metadata_uri: legacy.metadata_uri,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyCredential {
+    pub is_initialized: bool,
+    pub owner: Pubkey,
+    pub robot: Pubkey,
+    pub permission_level: PermissionLevel,
+    pub valid_from: UnixTimestamp,
+    pub valid_until: UnixTimestamp,
+    pub revoked: bool,
+    pub credential_hash: [u8; 32],
+    pub issuer: Pubkey,
+}
+
+impl LegacyCredential {
+    pub const LEN: usize = 1 + 32 + 32 + 1 + 8 + 8 + 1 + 32 + 32;
+}
+
+impl From<LegacyCredential> for Credential {
+    fn from(legacy: LegacyCredential) -> Self {
+        Self {
+            discriminator: Credential::DISCRIMINATOR,
+            version: Credential::VERSION,
+            owner: legacy.owner,
+            robot: legacy.robot,
+            permission_level: legacy.permission_level,
+            valid_from: legacy.valid_from,
+            valid_until: legacy.valid_until,
+            revoked: legacy.revoked,
+            credential_hash: legacy.credential_hash,
+            issuer: legacy.issuer,
+            payer: legacy.issuer,
+            parent_credential: None,
+            delegation_depth: 0,
+            scope: CredentialScope::default(),
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LegacyCommandLog {
+    pub is_initialized: bool,
+    pub robot: Pubkey,
+    pub executor: Pubkey,
+    pub command_type: CommandType,
+    pub timestamp: UnixTimestamp,
+    pub parameters: Vec<u8>,
+    pub success: bool,
+    pub error_code: u32,
+}
+
+impl LegacyCommandLog {
+    pub const LEN: usize = 1 + 32 + 32 + 1 + 8 + 256 + 1 + 4;
+}
+
+impl From<LegacyCommandLog> for CommandLog {
+    fn from(legacy: LegacyCommandLog) -> Self {
+        Self {
+            discriminator: CommandLog::DISCRIMINATOR,
+            version: CommandLog::VERSION,
+            robot: legacy.robot,
+            executor: legacy.executor,
+            command_type: legacy.command_type,
+            timestamp: legacy.timestamp,
+            sequence: 0,
+            parameters: legacy.parameters,
+            status: if legacy.success {
+                CommandStatus::Completed
+            } else {
+                CommandStatus::Failed
+            },
+            success: legacy.success,
+            error_code: legacy.error_code,
+        }
+    }
+}
+
+/// Reads a legacy account as `T`. The account must be at least `min_len`
+/// bytes, the layout's size when it was created, and hold nothing but zero
+/// padding after the serialized struct, so data of another type that only
+/// happens to deserialize as a prefix is rejected.
+pub fn unpack_legacy<T: BorshDeserialize>(data: &[u8], min_len: usize) -> Result<T, ProgramError> {
+    if data.len() < min_len {
+        return Err(RobyError::InvalidAccountData.into());
+    }
+    
+    let mut remaining = data;
+    let legacy = T::deserialize(&mut remaining).map_err(|_| RobyError::InvalidAccountData)?;
+    if remaining.iter().any(|byte| *byte != 0) {
+        return Err(RobyError::InvalidAccountData.into());
+    }
+    
+    Ok(legacy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn legacy_robot_account() -> Vec<u8> {
+        let mut robot_id = [0u8; 32];
+        robot_id[..5].copy_from_slice(b"arm-7");
+        let robot = LegacyRobot {
+            is_initialized: true,
+            owner: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            status: RobotStatus::Idle,
+            robot_id,
+            merkle_root: [7u8; 32],
+            last_command_timestamp: 0,
+            total_commands_executed: 0,
+            active_operators: Vec::new(),
+            max_operators: 10,
+            emergency_stop: false,
+            metadata_uri: String::new(),
+        };
+        
+        // The baseline SDK allocated 1024 bytes for robots.
+        let mut data = vec![0u8; 1024];
+        robot.serialize(&mut &mut data[..]).unwrap();
+        data
+    }
+    
+    #[test]
+    fn test_unpack_legacy_rejects_other_account_types() {
+        let data = legacy_robot_account();
+        
+        // Only a prefix of the robot reads as a command log.
+        assert!(LegacyCommandLog::deserialize(&mut &data[..]).is_ok());
+        assert!(unpack_legacy::<LegacyCommandLog>(&data, LegacyCommandLog::LEN).is_err());
+        assert!(unpack_legacy::<LegacyCredential>(&data, LegacyCredential::LEN).is_err());
+        
+        let robot = unpack_legacy::<LegacyRobot>(&data, LegacyRobot::LEN).unwrap();
+        assert_eq!(&robot.robot_id[..5], b"arm-7");
+        assert!(unpack_legacy::<LegacyRobot>(&data[..LegacyRobot::LEN - 1], LegacyRobot::LEN).is_err());
+    }
+}
+
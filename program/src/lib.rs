@@ -10,6 +10,9 @@ pub mod instruction;
 pub mod processor;
 pub mod state;
 pub mod merkle;
+pub mod legacy;
+pub mod utils;
+pub mod events;
 
 use crate::processor::Processor;
 
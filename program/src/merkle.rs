@@ -1,4 +1,35 @@
-use solana_program::keccak;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{clock::UnixTimestamp, keccak, pubkey::Pubkey};
+
+/// Prefix for leaf hashes. Leaves and internal nodes are hashed in separate
+/// domains so an internal node can never be presented as a leaf.
+pub const LEAF_PREFIX: u8 = 0x00;
+/// Prefix for internal node hashes.
+pub const NODE_PREFIX: u8 = 0x01;
+/// Padding leaf used to fill trees up to a power of two.
+pub const EMPTY_LEAF: [u8; 32] = [0u8; 32];
+
+/// How a credential tree combines sibling nodes.
+///
+/// Both schemes pad the leaves to a power of two with `EMPTY_LEAF`.
+/// `SortedPair` orders each pair before hashing, so proofs need no position
+/// and can be batched into a `MerkleMultiProof`. `Positional` hashes
+/// left-to-right, proofs carry the leaf index and batches are
+/// `PositionalMultiProof`s.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MerkleTreeScheme {
+    SortedPair,
+    Positional,
+}
+
+impl MerkleTreeScheme {
+    pub fn combine(&self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        match self {
+            MerkleTreeScheme::SortedPair => hash_sorted_pair(left, right),
+            MerkleTreeScheme::Positional => hash_pair(left, right),
+        }
+    }
+}
 
 pub struct MerkleProof {
     pub proof: Vec<[u8; 32]>,
@@ -7,57 +38,603 @@ pub struct MerkleProof {
 
 impl MerkleProof {
     pub fn verify(&self, root: &[u8; 32]) -> bool {
+        &self.compute_root() == root
+    }
+    
+    pub fn compute_root(&self) -> [u8; 32] {
+        let mut computed_hash = self.leaf;
+        
+        for proof_element in self.proof.iter() {
+            computed_hash = hash_sorted_pair(&computed_hash, proof_element);
+        }
+        
+        computed_hash
+    }
+}
+
+pub struct PositionalMerkleProof {
+    pub proof: Vec<[u8; 32]>,
+    pub leaf: [u8; 32],
+    pub index: u32,
+}
+
+impl PositionalMerkleProof {
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        self.compute_root().as_ref() == Some(root)
+    }
+    
+    /// Returns `None` when the index does not fit in a tree of the proof's depth.
+    pub fn compute_root(&self) -> Option<[u8; 32]> {
+        if self.proof.len() < 32 && self.index >> self.proof.len() != 0 {
+            return None;
+        }
+        
         let mut computed_hash = self.leaf;
+        let mut index = self.index;
         
         for proof_element in self.proof.iter() {
-            computed_hash = if computed_hash <= *proof_element {
+            computed_hash = if index & 1 == 0 {
                 hash_pair(&computed_hash, proof_element)
             } else {
                 hash_pair(proof_element, &computed_hash)
             };
+            index >>= 1;
         }
         
-        &computed_hash == root
+        Some(computed_hash)
     }
 }
 
 pub fn hash_leaf(data: &[u8]) -> [u8; 32] {
-    keccak::hash(data).to_bytes()
+    keccak::hashv(&[&[LEAF_PREFIX], data]).to_bytes()
 }
 
 pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
-    let mut combined = [0u8; 64];
-    combined[..32].copy_from_slice(a);
-    combined[32..].copy_from_slice(b);
-    keccak::hash(&combined).to_bytes()
+    keccak::hashv(&[&[NODE_PREFIX], a, b]).to_bytes()
+}
+
+pub fn hash_sorted_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    if a <= b {
+        hash_pair(a, b)
+    } else {
+        hash_pair(b, a)
+    }
+}
+
+/// Leaf committed to the credential tree for a single credential.
+///
+/// Binds the holder, the robot, the permission level and the validity
+/// window, so a leaf cannot be replayed for another robot or with a
+/// widened grant.
+pub fn credential_leaf(
+    owner: &Pubkey,
+    robot: &Pubkey,
+    permission_level: u8,
+    valid_from: UnixTimestamp,
+    valid_until: UnixTimestamp,
+    credential_hash: &[u8; 32],
+) -> [u8; 32] {
+    let mut data = [0u8; 32 + 32 + 1 + 8 + 8 + 32];
+    data[..32].copy_from_slice(owner.as_ref());
+    data[32..64].copy_from_slice(robot.as_ref());
+    data[64] = permission_level;
+    data[65..73].copy_from_slice(&valid_from.to_le_bytes());
+    data[73..81].copy_from_slice(&valid_until.to_le_bytes());
+    data[81..].copy_from_slice(credential_hash);
+    hash_leaf(&data)
 }
 
 pub fn compute_merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
-    if leaves.is_empty() {
-        return [0u8; 32];
+    MerkleTree::new(MerkleTreeScheme::SortedPair, leaves).root()
+}
+
+/// Full in-memory tree, used to build roots and proofs for either scheme.
+pub struct MerkleTree {
+    pub scheme: MerkleTreeScheme,
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    pub fn new(scheme: MerkleTreeScheme, leaves: &[[u8; 32]]) -> Self {
+        let mut current_level = leaves.to_vec();
+        current_level.resize(leaves.len().next_power_of_two(), EMPTY_LEAF);
+        
+        let mut levels = vec![current_level];
+        
+        while levels[levels.len() - 1].len() > 1 {
+            let next_level = levels[levels.len() - 1]
+                .chunks_exact(2)
+                .map(|pair| scheme.combine(&pair[0], &pair[1]))
+                .collect();
+            
+            levels.push(next_level);
+        }
+        
+        Self { scheme, levels }
+    }
+    
+    pub fn root(&self) -> [u8; 32] {
+        self.levels[self.levels.len() - 1]
+            .first()
+            .copied()
+            .unwrap_or(EMPTY_LEAF)
+    }
+    
+    pub fn proof(&self, index: usize) -> Option<Vec<[u8; 32]>> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+        
+        let mut proof = Vec::new();
+        let mut index = index;
+        
+        for level in &self.levels[..self.levels.len() - 1] {
+            proof.push(level[index ^ 1]);
+            index >>= 1;
+        }
+        
+        Some(proof)
+    }
+    
+    /// Builds a multiproof for the leaves at `indices` of a sorted-pair tree.
+    /// The leaves must later be supplied to `verify` in ascending index order.
+    pub fn multiproof(&self, indices: &[usize]) -> Option<MerkleMultiProof> {
+        if self.scheme != MerkleTreeScheme::SortedPair || indices.is_empty() {
+            return None;
+        }
+        
+        let mut known = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        if known[known.len() - 1] >= self.levels[0].len() {
+            return None;
+        }
+        
+        let mut proof = Vec::new();
+        let mut proof_flags = Vec::new();
+        
+        for level in &self.levels[..self.levels.len() - 1] {
+            let mut next_known = Vec::with_capacity(known.len());
+            let mut i = 0;
+            
+            while i < known.len() {
+                let index = known[i];
+                if i + 1 < known.len() && known[i + 1] == index ^ 1 {
+                    proof_flags.push(true);
+                    i += 2;
+                } else {
+                    proof_flags.push(false);
+                    proof.push(level[index ^ 1]);
+                    i += 1;
+                }
+                next_known.push(index >> 1);
+            }
+            
+            known = next_known;
+        }
+        
+        Some(MerkleMultiProof { proof, proof_flags })
+    }
+    
+    /// Builds a multiproof for the leaves at `indices` of a positional tree.
+    pub fn positional_multiproof(&self, indices: &[usize]) -> Option<PositionalMultiProof> {
+        if self.scheme != MerkleTreeScheme::Positional || indices.is_empty() {
+            return None;
+        }
+        
+        let mut known = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        if known[known.len() - 1] >= self.levels[0].len() {
+            return None;
+        }
+        
+        let leaf_indices = known.iter().map(|&index| index as u32).collect();
+        let mut proof = Vec::new();
+        
+        for level in &self.levels[..self.levels.len() - 1] {
+            let mut next_known = Vec::with_capacity(known.len());
+            let mut i = 0;
+            
+            while i < known.len() {
+                let index = known[i];
+                if i + 1 < known.len() && known[i + 1] == index ^ 1 {
+                    i += 2;
+                } else {
+                    proof.push(level[index ^ 1]);
+                    i += 1;
+                }
+                next_known.push(index >> 1);
+            }
+            
+            known = next_known;
+        }
+        
+        Some(PositionalMultiProof {
+            depth: (self.levels.len() - 1) as u8,
+            indices: leaf_indices,
+            proof,
+        })
+    }
+}
+
+/// Append-only positional tree of fixed depth.
+///
+/// Only the rightmost filled subtree at each level is stored, plus a ring
+/// buffer of recent roots so proofs built against a slightly older root keep
+/// verifying while new leaves are appended. Roots are identical to a
+/// positional `MerkleTree` over the same leaves padded to `2^depth`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct IncrementalMerkleTree {
+    pub depth: u8,
+    pub next_index: u64,
+    pub filled_subtrees: Vec<[u8; 32]>,
+    pub roots: Vec<[u8; 32]>,
+    pub current_root_index: u8,
+}
+
+impl IncrementalMerkleTree {
+    pub const MAX_DEPTH: u8 = 32;
+    pub const ROOT_HISTORY_SIZE: usize = 32;
+    pub const LEN: usize = 1 + 8
+        + (4 + 32 * Self::MAX_DEPTH as usize)
+        + (4 + 32 * Self::ROOT_HISTORY_SIZE)
+        + 1;
+    
+    pub fn new(depth: u8) -> Option<Self> {
+        if depth == 0 || depth > Self::MAX_DEPTH {
+            return None;
+        }
+        
+        let mut filled_subtrees = Vec::with_capacity(depth as usize);
+        let mut zero = EMPTY_LEAF;
+        for _ in 0..depth {
+            filled_subtrees.push(zero);
+            zero = hash_pair(&zero, &zero);
+        }
+        
+        let mut roots = vec![EMPTY_LEAF; Self::ROOT_HISTORY_SIZE];
+        roots[0] = zero;
+        
+        Some(Self {
+            depth,
+            next_index: 0,
+            filled_subtrees,
+            roots,
+            current_root_index: 0,
+        })
+    }
+    
+    pub fn root(&self) -> [u8; 32] {
+        self.roots[self.current_root_index as usize]
+    }
+    
+    /// Appends `leaf` and returns its index, or `None` once the tree is full.
+    pub fn append(&mut self, leaf: [u8; 32]) -> Option<u64> {
+        if self.next_index >= 1u64 << self.depth {
+            return None;
+        }
+        
+        let leaf_index = self.next_index;
+        let mut index = leaf_index;
+        let mut current = leaf;
+        let mut zero = EMPTY_LEAF;
+        
+        for level in 0..self.depth as usize {
+            current = if index & 1 == 0 {
+                self.filled_subtrees[level] = current;
+                hash_pair(&current, &zero)
+            } else {
+                hash_pair(&self.filled_subtrees[level], &current)
+            };
+            zero = hash_pair(&zero, &zero);
+            index >>= 1;
+        }
+        
+        self.current_root_index = ((self.current_root_index as usize + 1) % Self::ROOT_HISTORY_SIZE) as u8;
+        self.roots[self.current_root_index as usize] = current;
+        self.next_index += 1;
+        
+        Some(leaf_index)
     }
     
-    if leaves.len() == 1 {
-        return leaves[0];
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        *root != EMPTY_LEAF && self.roots.contains(root)
+    }
+}
+
+/// Proof for one key of a 256-level sparse Merkle tree.
+///
+/// The tree is keyed by the bits of a 32-byte key (most significant bit
+/// first from the root). Empty subtrees hash to `EMPTY_LEAF` at every
+/// height, so only non-empty siblings are carried: bit `h` of
+/// `sibling_bitmap` (byte `h / 8`, bit `h % 8`) is set when the sibling at
+/// height `h` is the next entry of `siblings`, height 0 being the leaf level.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct SparseMerkleProof {
+    pub sibling_bitmap: [u8; 32],
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl SparseMerkleProof {
+    pub const DEPTH: usize = 256;
+    
+    pub fn verify_non_membership(&self, root: &[u8; 32], key: &[u8; 32]) -> bool {
+        self.compute_root(key, EMPTY_LEAF).as_ref() == Some(root)
     }
     
-    let mut current_level = leaves.to_vec();
+    pub fn verify_membership(&self, root: &[u8; 32], key: &[u8; 32]) -> bool {
+        self.compute_root(key, sparse_leaf(key)).as_ref() == Some(root)
+    }
     
-    while current_level.len() > 1 {
-        let mut next_level = Vec::new();
+    /// Root of the tree in which the slot for `key` holds `value`. Returns
+    /// `None` if the bitmap and the sibling list disagree.
+    pub fn compute_root(&self, key: &[u8; 32], value: [u8; 32]) -> Option<[u8; 32]> {
+        let mut siblings = self.siblings.iter();
+        let mut current = value;
         
-        for i in (0..current_level.len()).step_by(2) {
-            if i + 1 < current_level.len() {
-                next_level.push(hash_pair(&current_level[i], &current_level[i + 1]));
+        for height in 0..Self::DEPTH {
+            let sibling = if self.sibling_bitmap[height / 8] & (1 << (height % 8)) != 0 {
+                *siblings.next()?
             } else {
-                next_level.push(current_level[i]);
+                EMPTY_LEAF
+            };
+            
+            current = if key_bit(key, Self::DEPTH - 1 - height) {
+                hash_sparse_pair(&sibling, &current)
+            } else {
+                hash_sparse_pair(&current, &sibling)
+            };
+        }
+        
+        if siblings.next().is_some() {
+            return None;
+        }
+        
+        Some(current)
+    }
+}
+
+/// Non-membership proof for several keys of the same sparse Merkle tree.
+///
+/// The keys' paths are hashed together level by level from the leaves up,
+/// in ascending key order. Every node whose sibling is not itself on one of
+/// the paths takes the next sibling slot: slots listed in `sibling_slots`
+/// (ascending) take the matching entry of `siblings` and all others are
+/// `EMPTY_LEAF`. Non-empty siblings shared by several paths are carried once.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct SparseMerkleMultiProof {
+    pub sibling_slots: Vec<u16>,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl SparseMerkleMultiProof {
+    pub fn verify_non_membership(&self, root: &[u8; 32], keys: &[[u8; 32]]) -> bool {
+        self.compute_root(keys).as_ref() == Some(root)
+    }
+    
+    /// Root of the tree in which every slot for `keys` is empty. Returns
+    /// `None` if the slots and siblings do not line up with the paths.
+    pub fn compute_root(&self, keys: &[[u8; 32]]) -> Option<[u8; 32]> {
+        if keys.is_empty() || self.sibling_slots.len() != self.siblings.len() {
+            return None;
+        }
+        
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+        
+        let mut nodes: Vec<([u8; 32], [u8; 32])> =
+            sorted_keys.into_iter().map(|key| (key, EMPTY_LEAF)).collect();
+        let mut provided = self.sibling_slots.iter().zip(self.siblings.iter()).peekable();
+        let mut slot: u32 = 0;
+        
+        for height in 0..SparseMerkleProof::DEPTH {
+            let bit = SparseMerkleProof::DEPTH - 1 - height;
+            let mut next_nodes = Vec::with_capacity(nodes.len());
+            let mut i = 0;
+            
+            while i < nodes.len() {
+                let (path, hash) = nodes[i];
+                let parent_path = clear_key_bit(&path, bit);
+                
+                let parent = if i + 1 < nodes.len() && clear_key_bit(&nodes[i + 1].0, bit) == parent_path {
+                    i += 2;
+                    hash_sparse_pair(&hash, &nodes[i - 1].1)
+                } else {
+                    i += 1;
+                    let sibling = match provided.peek() {
+                        Some((&next_slot, &sibling)) if next_slot as u32 == slot => {
+                            provided.next();
+                            sibling
+                        }
+                        _ => EMPTY_LEAF,
+                    };
+                    slot += 1;
+                    
+                    if key_bit(&path, bit) {
+                        hash_sparse_pair(&sibling, &hash)
+                    } else {
+                        hash_sparse_pair(&hash, &sibling)
+                    }
+                };
+                next_nodes.push((parent_path, parent));
             }
+            
+            nodes = next_nodes;
+        }
+        
+        if provided.next().is_some() {
+            return None;
         }
         
-        current_level = next_level;
+        Some(nodes[0].1)
+    }
+}
+
+/// Value stored in a sparse tree slot whose key is present.
+pub fn sparse_leaf(key: &[u8; 32]) -> [u8; 32] {
+    hash_leaf(key)
+}
+
+pub fn hash_sparse_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    if *left == EMPTY_LEAF && *right == EMPTY_LEAF {
+        EMPTY_LEAF
+    } else {
+        hash_pair(left, right)
+    }
+}
+
+fn key_bit(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn clear_key_bit(key: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut cleared = *key;
+    cleared[depth / 8] &= !(0x80 >> (depth % 8));
+    cleared
+}
+
+/// Proof that several leaves belong to the same sorted-pair tree.
+///
+/// Siblings shared between the leaves' paths are only included once. Each
+/// flag describes one hash step: `true` combines two nodes that are already
+/// known (leaves or earlier results), `false` combines the next known node
+/// with the next element of `proof`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct MerkleMultiProof {
+    pub proof: Vec<[u8; 32]>,
+    pub proof_flags: Vec<bool>,
+}
+
+impl MerkleMultiProof {
+    pub fn verify(&self, root: &[u8; 32], leaves: &[[u8; 32]]) -> bool {
+        let total_hashes = self.proof_flags.len();
+        if leaves.is_empty() || leaves.len() + self.proof.len() != total_hashes + 1 {
+            return false;
+        }
+        
+        if total_hashes == 0 {
+            return leaves[0] == *root;
+        }
+        
+        let mut hashes: Vec<[u8; 32]> = Vec::with_capacity(total_hashes);
+        let mut leaf_pos = 0;
+        let mut hash_pos = 0;
+        let mut proof_pos = 0;
+        
+        for &flag in self.proof_flags.iter() {
+            let a = match Self::next_known(leaves, &hashes, &mut leaf_pos, &mut hash_pos) {
+                Some(a) => a,
+                None => return false,
+            };
+            let b = if flag {
+                match Self::next_known(leaves, &hashes, &mut leaf_pos, &mut hash_pos) {
+                    Some(b) => b,
+                    None => return false,
+                }
+            } else {
+                match self.proof.get(proof_pos) {
+                    Some(b) => {
+                        proof_pos += 1;
+                        *b
+                    }
+                    None => return false,
+                }
+            };
+            hashes.push(hash_sorted_pair(&a, &b));
+        }
+        
+        proof_pos == self.proof.len() && hashes[total_hashes - 1] == *root
     }
     
-    current_level[0]
+    fn next_known(
+        leaves: &[[u8; 32]],
+        hashes: &[[u8; 32]],
+        leaf_pos: &mut usize,
+        hash_pos: &mut usize,
+    ) -> Option<[u8; 32]> {
+        if *leaf_pos < leaves.len() {
+            *leaf_pos += 1;
+            Some(leaves[*leaf_pos - 1])
+        } else if *hash_pos < hashes.len() {
+            *hash_pos += 1;
+            Some(hashes[*hash_pos - 1])
+        } else {
+            None
+        }
+    }
+}
+
+/// Proof that several leaves belong to the same positional tree of
+/// `depth` levels, such as a robot's on-chain `IncrementalMerkleTree`.
+///
+/// Leaves are supplied in the order of `indices`, which must be strictly
+/// ascending. Level by level from the leaves up, each known node whose
+/// sibling is not also known consumes the next element of `proof`, so
+/// shared siblings are only included once.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct PositionalMultiProof {
+    pub depth: u8,
+    pub indices: Vec<u32>,
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl PositionalMultiProof {
+    pub fn verify(&self, root: &[u8; 32], leaves: &[[u8; 32]]) -> bool {
+        self.compute_root(leaves).as_ref() == Some(root)
+    }
+    
+    /// Returns `None` if the indices are out of order or do not fit the
+    /// depth, or if the proof has the wrong number of siblings.
+    pub fn compute_root(&self, leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+        let depth = self.depth as usize;
+        if leaves.is_empty() || leaves.len() != self.indices.len() || depth > 32 {
+            return None;
+        }
+        
+        if !self.indices.windows(2).all(|pair| pair[0] < pair[1]) {
+            return None;
+        }
+        
+        if depth < 32 && self.indices[self.indices.len() - 1] >> depth != 0 {
+            return None;
+        }
+        
+        let mut known: Vec<(u32, [u8; 32])> =
+            self.indices.iter().copied().zip(leaves.iter().copied()).collect();
+        let mut proof = self.proof.iter();
+        
+        for _ in 0..depth {
+            let mut next_known = Vec::with_capacity(known.len());
+            let mut i = 0;
+            
+            while i < known.len() {
+                let (index, hash) = known[i];
+                let parent = if i + 1 < known.len() && known[i + 1].0 == index ^ 1 {
+                    i += 2;
+                    hash_pair(&hash, &known[i - 1].1)
+                } else {
+                    i += 1;
+                    let sibling = proof.next()?;
+                    if index & 1 == 0 {
+                        hash_pair(&hash, sibling)
+                    } else {
+                        hash_pair(sibling, &hash)
+                    }
+                };
+                next_known.push((index >> 1, parent));
+            }
+            
+            known = next_known;
+        }
+        
+        if proof.next().is_some() {
+            return None;
+        }
+        
+        Some(known[0].1)
+    }
 }
 
 #[cfg(test)]
@@ -76,7 +653,7 @@ mod tests {
         
         let proof_for_leaf1 = vec![
             leaf2,
-            hash_pair(&leaf3, &leaf4),
+            hash_sorted_pair(&leaf3, &leaf4),
         ];
         
         let merkle_proof = MerkleProof {
@@ -103,6 +680,364 @@ mod tests {
         
         assert!(!merkle_proof.verify(&root));
     }
+    
+    #[test]
+    fn test_leaf_and_node_hashes_are_domain_separated() {
+        let leaf1 = hash_leaf(b"credential_1");
+        let leaf2 = hash_leaf(b"credential_2");
+        
+        let mut concatenated = [0u8; 64];
+        concatenated[..32].copy_from_slice(&leaf1);
+        concatenated[32..].copy_from_slice(&leaf2);
+        
+        assert_ne!(hash_leaf(&concatenated), hash_pair(&leaf1, &leaf2));
+    }
+    
+    #[test]
+    fn test_credential_leaf_binds_every_field() {
+        let owner = Pubkey::new_unique();
+        let robot = Pubkey::new_unique();
+        let hash = [7u8; 32];
+        let leaf = credential_leaf(&owner, &robot, 2, 100, 200, &hash);
+        
+        assert_ne!(leaf, credential_leaf(&robot, &owner, 2, 100, 200, &hash));
+        assert_ne!(leaf, credential_leaf(&owner, &robot, 3, 100, 200, &hash));
+        assert_ne!(leaf, credential_leaf(&owner, &robot, 2, 99, 200, &hash));
+        assert_ne!(leaf, credential_leaf(&owner, &robot, 2, 100, 201, &hash));
+        assert_ne!(leaf, credential_leaf(&owner, &robot, 2, 100, 200, &[8u8; 32]));
+    }
+    
+    fn sample_indices(size: usize) -> Vec<usize> {
+        if size <= 64 {
+            return (0..size).collect();
+        }
+        
+        let mut indices: Vec<usize> = (0..size).step_by(size / 7).collect();
+        indices.extend([size / 2, size - 2, size - 1]);
+        indices
+    }
+    
+    #[test]
+    fn test_round_trip_for_every_tree_size() {
+        let all_leaves: Vec<[u8; 32]> = (0u32..1024)
+            .map(|i| hash_leaf(&i.to_le_bytes()))
+            .collect();
+        
+        for size in 1..=all_leaves.len() {
+            let leaves = &all_leaves[..size];
+            
+            let sorted = MerkleTree::new(MerkleTreeScheme::SortedPair, leaves);
+            let positional = MerkleTree::new(MerkleTreeScheme::Positional, leaves);
+            assert_eq!(sorted.root(), compute_merkle_root(leaves));
+            
+            for index in sample_indices(size) {
+                let sorted_proof = MerkleProof {
+                    proof: sorted.proof(index).unwrap(),
+                    leaf: leaves[index],
+                };
+                assert!(sorted_proof.verify(&sorted.root()), "sorted size {} index {}", size, index);
+                
+                let positional_proof = PositionalMerkleProof {
+                    proof: positional.proof(index).unwrap(),
+                    leaf: leaves[index],
+                    index: index as u32,
+                };
+                assert!(
+                    positional_proof.verify(&positional.root()),
+                    "positional size {} index {}",
+                    size,
+                    index
+                );
+            }
+        }
+    }
+    
+    #[test]
+    fn test_positional_proof_rejects_wrong_index() {
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let tree = MerkleTree::new(MerkleTreeScheme::Positional, &leaves);
+        let proof = tree.proof(2).unwrap();
+        
+        for index in [1u32, 3, 10] {
+            let wrong = PositionalMerkleProof {
+                proof: proof.clone(),
+                leaf: leaves[2],
+                index,
+            };
+            assert!(!wrong.verify(&tree.root()));
+        }
+    }
+    
+    #[test]
+    fn test_multiproof_round_trip() {
+        let leaves: Vec<[u8; 32]> = (0u32..37).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let tree = MerkleTree::new(MerkleTreeScheme::SortedPair, &leaves);
+        
+        let subsets: [&[usize]; 5] = [
+            &[0],
+            &[36],
+            &[0, 1, 2, 3],
+            &[3, 8, 9, 20, 36],
+            &[1, 5, 6, 7, 10, 11, 12, 13, 14, 15, 16, 17, 30, 31, 32, 35],
+        ];
+        
+        for indices in subsets {
+            let multiproof = tree.multiproof(indices).unwrap();
+            let batch: Vec<[u8; 32]> = indices.iter().map(|&i| leaves[i]).collect();
+            
+            assert!(multiproof.verify(&tree.root(), &batch), "indices {:?}", indices);
+            
+            let single_proof_len = tree.proof(0).unwrap().len() * indices.len();
+            assert!(multiproof.proof.len() <= single_proof_len);
+        }
+    }
+    
+    #[test]
+    fn test_multiproof_rejects_tampering() {
+        let leaves: Vec<[u8; 32]> = (0u32..16).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let tree = MerkleTree::new(MerkleTreeScheme::SortedPair, &leaves);
+        let indices = [2usize, 3, 9, 14];
+        let multiproof = tree.multiproof(&indices).unwrap();
+        let batch: Vec<[u8; 32]> = indices.iter().map(|&i| leaves[i]).collect();
+        
+        let mut wrong_leaf = batch.clone();
+        wrong_leaf[2] = hash_leaf(b"intruder");
+        assert!(!multiproof.verify(&tree.root(), &wrong_leaf));
+        
+        assert!(!multiproof.verify(&tree.root(), &batch[..3]));
+        
+        let mut flipped = multiproof.clone();
+        flipped.proof_flags[0] = !flipped.proof_flags[0];
+        assert!(!flipped.verify(&tree.root(), &batch));
+        
+        let mut extra = multiproof.clone();
+        extra.proof.push(leaves[0]);
+        assert!(!extra.verify(&tree.root(), &batch));
+    }
+    
+    #[test]
+    fn test_positional_multiproof_matches_incremental_tree() {
+        let depth = 5;
+        let leaves: Vec<[u8; 32]> = (0u32..23).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let mut incremental = IncrementalMerkleTree::new(depth).unwrap();
+        for leaf in &leaves {
+            incremental.append(*leaf).unwrap();
+        }
+        
+        let mut padded = leaves.clone();
+        padded.resize(1 << depth, EMPTY_LEAF);
+        let tree = MerkleTree::new(MerkleTreeScheme::Positional, &padded);
+        assert_eq!(tree.root(), incremental.root());
+        
+        let indices = [0usize, 1, 6, 13, 22];
+        let multiproof = tree.positional_multiproof(&indices).unwrap();
+        let batch: Vec<[u8; 32]> = indices.iter().map(|&i| leaves[i]).collect();
+        assert!(multiproof.verify(&incremental.root(), &batch));
+        
+        let mut swapped = batch.clone();
+        swapped.swap(1, 2);
+        assert!(!multiproof.verify(&incremental.root(), &swapped));
+        
+        let mut shifted = multiproof.clone();
+        shifted.indices[4] = 23;
+        assert!(!shifted.verify(&incremental.root(), &batch));
+        
+        let mut extra = multiproof.clone();
+        extra.proof.push(leaves[0]);
+        assert!(!extra.verify(&incremental.root(), &batch));
+    }
+    
+    #[test]
+    fn test_incremental_tree_matches_full_tree() {
+        let depth = 6;
+        let mut incremental = IncrementalMerkleTree::new(depth).unwrap();
+        let mut leaves = Vec::new();
+        
+        assert_eq!(
+            incremental.root(),
+            MerkleTree::new(MerkleTreeScheme::Positional, &[EMPTY_LEAF; 64]).root()
+        );
+        
+        for i in 0u32..64 {
+            let leaf = hash_leaf(&i.to_le_bytes());
+            assert_eq!(incremental.append(leaf), Some(i as u64));
+            leaves.push(leaf);
+            
+            let mut padded = leaves.clone();
+            padded.resize(1 << depth, EMPTY_LEAF);
+            let full = MerkleTree::new(MerkleTreeScheme::Positional, &padded);
+            assert_eq!(incremental.root(), full.root());
+            
+            let proof = PositionalMerkleProof {
+                proof: full.proof(0).unwrap(),
+                leaf: leaves[0],
+                index: 0,
+            };
+            assert!(incremental.is_known_root(&proof.compute_root().unwrap()));
+        }
+        
+        assert_eq!(incremental.append(hash_leaf(b"overflow")), None);
+    }
+    
+    #[test]
+    fn test_incremental_tree_forgets_old_roots() {
+        let mut tree = IncrementalMerkleTree::new(8).unwrap();
+        tree.append(hash_leaf(b"first")).unwrap();
+        let first_root = tree.root();
+        
+        for i in 0..IncrementalMerkleTree::ROOT_HISTORY_SIZE - 1 {
+            tree.append(hash_leaf(&(i as u32).to_le_bytes())).unwrap();
+            assert!(tree.is_known_root(&first_root));
+        }
+        
+        tree.append(hash_leaf(b"one more")).unwrap();
+        assert!(!tree.is_known_root(&first_root));
+        assert!(!tree.is_known_root(&EMPTY_LEAF));
+    }
+    
+    fn sparse_subtree(keys: &[[u8; 32]], depth: usize) -> [u8; 32] {
+        if keys.is_empty() {
+            return EMPTY_LEAF;
+        }
+        if depth == SparseMerkleProof::DEPTH {
+            return sparse_leaf(&keys[0]);
+        }
+        
+        let (right, left): (Vec<[u8; 32]>, Vec<[u8; 32]>) =
+            keys.iter().partition(|key| key_bit(key, depth));
+        hash_sparse_pair(&sparse_subtree(&left, depth + 1), &sparse_subtree(&right, depth + 1))
+    }
+    
+    fn sparse_proof(keys: &[[u8; 32]], key: &[u8; 32]) -> SparseMerkleProof {
+        let mut proof = SparseMerkleProof::default();
+        let mut path_keys = keys.to_vec();
+        let mut siblings_from_root = Vec::new();
+        
+        for depth in 0..SparseMerkleProof::DEPTH {
+            let bit = key_bit(key, depth);
+            let (same, other): (Vec<[u8; 32]>, Vec<[u8; 32]>) =
+                path_keys.iter().partition(|k| key_bit(k, depth) == bit);
+            siblings_from_root.push(sparse_subtree(&other, depth + 1));
+            path_keys = same;
+        }
+        
+        for (height, sibling) in siblings_from_root.iter().rev().enumerate() {
+            if *sibling != EMPTY_LEAF {
+                proof.sibling_bitmap[height / 8] |= 1 << (height % 8);
+                proof.siblings.push(*sibling);
+            }
+        }
+        
+        proof
+    }
+    
+    fn sparse_multiproof(keys: &[[u8; 32]], absent: &[[u8; 32]]) -> SparseMerkleMultiProof {
+        let mut proof = SparseMerkleMultiProof::default();
+        let mut paths = absent.to_vec();
+        paths.sort_unstable();
+        paths.dedup();
+        let mut slot = 0u16;
+        
+        for height in 0..SparseMerkleProof::DEPTH {
+            let bit = SparseMerkleProof::DEPTH - 1 - height;
+            let mut next_paths = Vec::with_capacity(paths.len());
+            let mut i = 0;
+            
+            while i < paths.len() {
+                let parent = clear_key_bit(&paths[i], bit);
+                if i + 1 < paths.len() && clear_key_bit(&paths[i + 1], bit) == parent {
+                    i += 2;
+                } else {
+                    let sibling_keys: Vec<[u8; 32]> = keys
+                        .iter()
+                        .filter(|key| {
+                            (0..bit).all(|depth| key_bit(key, depth) == key_bit(&paths[i], depth))
+                                && key_bit(key, bit) != key_bit(&paths[i], bit)
+                        })
+                        .copied()
+                        .collect();
+                    let sibling = sparse_subtree(&sibling_keys, bit + 1);
+                    if sibling != EMPTY_LEAF {
+                        proof.sibling_slots.push(slot);
+                        proof.siblings.push(sibling);
+                    }
+                    slot += 1;
+                    i += 1;
+                }
+                next_paths.push(parent);
+            }
+            
+            paths = next_paths;
+        }
+        
+        proof
+    }
+    
+    #[test]
+    fn test_sparse_tree_non_membership() {
+        let revoked: Vec<[u8; 32]> = (0u32..20).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let root = sparse_subtree(&revoked, 0);
+        
+        let active = hash_leaf(b"still valid");
+        let proof = sparse_proof(&revoked, &active);
+        assert!(proof.verify_non_membership(&root, &active));
+        assert!(!proof.verify_membership(&root, &active));
+        
+        for key in &revoked {
+            let proof = sparse_proof(&revoked, key);
+            assert!(proof.verify_membership(&root, key));
+            assert!(!proof.verify_non_membership(&root, key));
+        }
+    }
+    
+    #[test]
+    fn test_sparse_tree_empty_root_needs_no_siblings() {
+        let key = hash_leaf(b"anything");
+        let proof = SparseMerkleProof::default();
+        
+        assert!(proof.verify_non_membership(&EMPTY_LEAF, &key));
+        assert!(!proof.verify_non_membership(&sparse_subtree(&[key], 0), &key));
+    }
+    
+    #[test]
+    fn test_sparse_proof_rejects_mismatched_bitmap() {
+        let revoked: Vec<[u8; 32]> = (0u32..4).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let root = sparse_subtree(&revoked, 0);
+        let key = hash_leaf(b"active");
+        
+        let mut proof = sparse_proof(&revoked, &key);
+        proof.siblings.push(EMPTY_LEAF);
+        assert!(!proof.verify_non_membership(&root, &key));
+    }
+    
+    #[test]
+    fn test_sparse_multiproof_non_membership() {
+        let revoked: Vec<[u8; 32]> = (0u32..20).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        let root = sparse_subtree(&revoked, 0);
+        let active: Vec<[u8; 32]> = (100u32..116).map(|i| hash_leaf(&i.to_le_bytes())).collect();
+        
+        let multiproof = sparse_multiproof(&revoked, &active);
+        assert!(multiproof.verify_non_membership(&root, &active));
+        
+        let single_siblings: usize = active
+            .iter()
+            .map(|key| sparse_proof(&revoked, key).siblings.len())
+            .sum();
+        assert!(multiproof.siblings.len() < single_siblings);
+        
+        let mut with_revoked = active.clone();
+        with_revoked[3] = revoked[7];
+        let forged = sparse_multiproof(&revoked, &with_revoked);
+        assert!(!forged.verify_non_membership(&root, &with_revoked));
+        
+        assert!(!multiproof.verify_non_membership(&root, &active[..15]));
+        
+        let mut shifted = multiproof.clone();
+        shifted.sibling_slots[0] += 1;
+        assert!(!shifted.verify_non_membership(&root, &active));
+        
+        assert!(SparseMerkleMultiProof::default().verify_non_membership(&EMPTY_LEAF, &active));
+    }
 }
 
 
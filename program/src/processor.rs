@@ -1,21 +1,33 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshSerialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    program::invoke,
     program_error::ProgramError,
-    program_pack::IsInitialized,
     pubkey::Pubkey,
     rent::Rent,
     sysvar::Sysvar,
-    clock::Clock,
+    clock::{Clock, UnixTimestamp},
+    system_instruction,
+    system_program,
 };
 
 use crate::{
     error::RobyError,
-    instruction::RobyInstruction,
-    state::{Robot, Credential, CommandLog, RobotStatus, PermissionLevel, CommandType},
-    merkle::MerkleProof,
+    events::{Event, StatusChanged},
+    instruction::{RobyInstruction, CredentialProof, CredentialMultiProof},
+    state::{
+        Robot, Credential, CommandLog, CredentialTree, CommandQueue, QueuedCommand, ResumeProposal,
+        MultisigAuthority, Proposal, OperatorPolicy, RobotStatus, CredentialScope,
+        PermissionLevel, CommandType, CommandStatus, AccountType, RobyAccount, DISCRIMINATOR_LEN,
+    },
+    legacy::{unpack_legacy, LegacyRobot, LegacyCredential, LegacyCommandLog},
+    merkle::{
+        MerkleProof, PositionalMerkleProof, MerkleTreeScheme,
+        IncrementalMerkleTree, SparseMerkleMultiProof,
+    },
+    utils::{create_pda_account, close_account},
 };
 
 pub struct Processor;
@@ -28,11 +40,25 @@ impl Processor {
     ) -> ProgramResult {
         let instruction = RobyInstruction::unpack(instruction_data)?;
         
+        if instruction.requires_proposal() {
+            Self::check_proposal_gate(program_id, accounts)?;
+        }
+        
+        Self::dispatch(program_id, accounts, instruction)
+    }
+    
+    fn dispatch(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction: RobyInstruction,
+    ) -> ProgramResult {
         match instruction {
             RobyInstruction::InitializeRobot {
                 robot_id,
                 merkle_root,
+                tree_scheme,
                 metadata_uri,
+                device_key,
             } => {
                 msg!("Instruction: InitializeRobot");
                 Self::process_initialize_robot(
@@ -40,7 +66,9 @@ impl Processor {
                     program_id,
                     robot_id,
                     merkle_root,
+                    tree_scheme,
                     metadata_uri,
+                    device_key,
                 )
             }
             RobyInstruction::IssueCredential {
@@ -48,6 +76,9 @@ impl Processor {
                 valid_from,
                 valid_until,
                 credential_hash,
+                scope,
+                append_to_tree,
+                admin_proof,
             } => {
                 msg!("Instruction: IssueCredential");
                 Self::process_issue_credential(
@@ -57,16 +88,20 @@ impl Processor {
                     valid_from,
                     valid_until,
                     credential_hash,
+                    scope,
+                    append_to_tree,
+                    admin_proof,
                 )
             }
-            RobyInstruction::RevokeCredential => {
+            RobyInstruction::RevokeCredential { admin_proof } => {
                 msg!("Instruction: RevokeCredential");
-                Self::process_revoke_credential(accounts, program_id)
+                Self::process_revoke_credential(accounts, program_id, admin_proof)
             }
             RobyInstruction::ExecuteCommand {
                 command_type,
                 parameters,
-                merkle_proof,
+                proof,
+                expected_sequence,
             } => {
                 msg!("Instruction: ExecuteCommand");
                 Self::process_execute_command(
@@ -74,41 +109,208 @@ impl Processor {
                     program_id,
                     command_type,
                     parameters,
-                    merkle_proof,
+                    proof,
+                    expected_sequence,
                 )
             }
-            RobyInstruction::UpdateMerkleRoot { new_merkle_root } => {
+            RobyInstruction::UpdateMerkleRoot { new_merkle_root, tree_scheme } => {
                 msg!("Instruction: UpdateMerkleRoot");
-                Self::process_update_merkle_root(accounts, program_id, new_merkle_root)
+                Self::process_update_merkle_root(accounts, program_id, new_merkle_root, tree_scheme)
             }
             RobyInstruction::TransferAuthority { new_authority } => {
                 msg!("Instruction: TransferAuthority");
                 Self::process_transfer_authority(accounts, program_id, new_authority)
             }
-            RobyInstruction::AddOperator { operator } => {
+            RobyInstruction::AddOperator { operator, admin_proof } => {
                 msg!("Instruction: AddOperator");
-                Self::process_add_operator(accounts, program_id, operator)
+                Self::process_add_operator(accounts, program_id, operator, admin_proof)
             }
-            RobyInstruction::RemoveOperator { operator } => {
+            RobyInstruction::RemoveOperator { operator, admin_proof } => {
                 msg!("Instruction: RemoveOperator");
-                Self::process_remove_operator(accounts, program_id, operator)
+                Self::process_remove_operator(accounts, program_id, operator, admin_proof)
             }
-            RobyInstruction::EmergencyStop => {
+            RobyInstruction::EmergencyStop { reason_code } => {
                 msg!("Instruction: EmergencyStop");
-                Self::process_emergency_stop(accounts, program_id)
+                Self::process_emergency_stop(accounts, program_id, reason_code)
             }
             RobyInstruction::Resume => {
                 msg!("Instruction: Resume");
                 Self::process_resume(accounts, program_id)
             }
-            RobyInstruction::UpdateRobotStatus { status } => {
+            RobyInstruction::UpdateRobotStatus { status, admin_proof } => {
                 msg!("Instruction: UpdateRobotStatus");
-                Self::process_update_robot_status(accounts, program_id, status)
+                Self::process_update_robot_status(accounts, program_id, status, admin_proof)
             }
             RobyInstruction::TransferOwnership { new_owner } => {
                 msg!("Instruction: TransferOwnership");
                 Self::process_transfer_ownership(accounts, program_id, new_owner)
             }
+            RobyInstruction::CloseRobot => {
+                msg!("Instruction: CloseRobot");
+                Self::process_close_robot(accounts, program_id)
+            }
+            RobyInstruction::CloseCredential => {
+                msg!("Instruction: CloseCredential");
+                Self::process_close_credential(accounts, program_id)
+            }
+            RobyInstruction::CloseCommandLog => {
+                msg!("Instruction: CloseCommandLog");
+                Self::process_close_command_log(accounts, program_id)
+            }
+            RobyInstruction::MigrateAccount { account_type } => {
+                msg!("Instruction: MigrateAccount");
+                Self::process_migrate_account(accounts, program_id, account_type)
+            }
+            RobyInstruction::VerifyCredentials {
+                multiproof,
+                revocation_proof,
+            } => {
+                msg!("Instruction: VerifyCredentials");
+                Self::process_verify_credentials(accounts, program_id, multiproof, revocation_proof)
+            }
+            RobyInstruction::InitializeCredentialTree { depth } => {
+                msg!("Instruction: InitializeCredentialTree");
+                Self::process_initialize_credential_tree(accounts, program_id, depth)
+            }
+            RobyInstruction::UpdateRevocationRoot { new_revocation_root } => {
+                msg!("Instruction: UpdateRevocationRoot");
+                Self::process_update_revocation_root(accounts, program_id, new_revocation_root)
+            }
+            RobyInstruction::SetRootGracePeriod { grace_period } => {
+                msg!("Instruction: SetRootGracePeriod");
+                Self::process_set_root_grace_period(accounts, program_id, grace_period)
+            }
+            RobyInstruction::InitializeCommandQueue => {
+                msg!("Instruction: InitializeCommandQueue");
+                Self::process_initialize_command_queue(accounts, program_id)
+            }
+            RobyInstruction::AcknowledgeCommand {
+                sequence,
+                status,
+                error_code,
+            } => {
+                msg!("Instruction: AcknowledgeCommand");
+                Self::process_acknowledge_command(accounts, program_id, sequence, status, error_code)
+            }
+            RobyInstruction::RotateDeviceKey { new_device_key, co_signed } => {
+                msg!("Instruction: RotateDeviceKey");
+                Self::process_rotate_device_key(accounts, program_id, new_device_key, co_signed)
+            }
+            RobyInstruction::ReportStatus { status } => {
+                msg!("Instruction: ReportStatus");
+                Self::process_report_status(accounts, program_id, status)
+            }
+            RobyInstruction::Heartbeat {
+                battery_level,
+                health_flags,
+            } => {
+                msg!("Instruction: Heartbeat");
+                Self::process_heartbeat(accounts, program_id, battery_level, health_flags)
+            }
+            RobyInstruction::SetLivenessTimeout { liveness_timeout } => {
+                msg!("Instruction: SetLivenessTimeout");
+                Self::process_set_liveness_timeout(accounts, program_id, liveness_timeout)
+            }
+            RobyInstruction::AddGuardian { guardian } => {
+                msg!("Instruction: AddGuardian");
+                Self::process_add_guardian(accounts, program_id, guardian)
+            }
+            RobyInstruction::RemoveGuardian { guardian } => {
+                msg!("Instruction: RemoveGuardian");
+                Self::process_remove_guardian(accounts, program_id, guardian)
+            }
+            RobyInstruction::SetResumePolicy {
+                resume_threshold,
+                proposal_ttl,
+            } => {
+                msg!("Instruction: SetResumePolicy");
+                Self::process_set_resume_policy(accounts, program_id, resume_threshold, proposal_ttl)
+            }
+            RobyInstruction::ProposeResume => {
+                msg!("Instruction: ProposeResume");
+                Self::process_propose_resume(accounts, program_id)
+            }
+            RobyInstruction::ApproveResume => {
+                msg!("Instruction: ApproveResume");
+                Self::process_approve_resume(accounts, program_id)
+            }
+            RobyInstruction::InitializeMultisig {
+                multisig_id,
+                signers,
+                threshold,
+            } => {
+                msg!("Instruction: InitializeMultisig");
+                Self::process_initialize_multisig(accounts, program_id, multisig_id, signers, threshold)
+            }
+            RobyInstruction::ConfigureProposals {
+                approvers,
+                threshold,
+                timelock,
+            } => {
+                msg!("Instruction: ConfigureProposals");
+                Self::process_configure_proposals(accounts, program_id, approvers, threshold, timelock)
+            }
+            RobyInstruction::CreateProposal { instruction_data } => {
+                msg!("Instruction: CreateProposal");
+                Self::process_create_proposal(accounts, program_id, instruction_data)
+            }
+            RobyInstruction::ApproveProposal => {
+                msg!("Instruction: ApproveProposal");
+                Self::process_approve_proposal(accounts, program_id)
+            }
+            RobyInstruction::CancelProposal => {
+                msg!("Instruction: CancelProposal");
+                Self::process_cancel_proposal(accounts, program_id)
+            }
+            RobyInstruction::ExecuteProposal => {
+                msg!("Instruction: ExecuteProposal");
+                Self::process_execute_proposal(accounts, program_id)
+            }
+            RobyInstruction::AcceptOwnership => {
+                msg!("Instruction: AcceptOwnership");
+                Self::process_accept_ownership(accounts, program_id)
+            }
+            RobyInstruction::AcceptAuthority => {
+                msg!("Instruction: AcceptAuthority");
+                Self::process_accept_authority(accounts, program_id)
+            }
+            RobyInstruction::CancelTransfer => {
+                msg!("Instruction: CancelTransfer");
+                Self::process_cancel_transfer(accounts, program_id)
+            }
+            RobyInstruction::SetOperatorPolicy { policy } => {
+                msg!("Instruction: SetOperatorPolicy");
+                Self::process_set_operator_policy(accounts, program_id, policy)
+            }
+            RobyInstruction::SetCommandPermission {
+                command_type,
+                min_level,
+            } => {
+                msg!("Instruction: SetCommandPermission");
+                Self::process_set_command_permission(accounts, program_id, command_type, min_level)
+            }
+            RobyInstruction::DelegateCredential {
+                permission_level,
+                valid_from,
+                valid_until,
+                credential_hash,
+                scope,
+            } => {
+                msg!("Instruction: DelegateCredential");
+                Self::process_delegate_credential(
+                    accounts,
+                    program_id,
+                    permission_level,
+                    valid_from,
+                    valid_until,
+                    credential_hash,
+                    scope,
+                )
+            }
+            RobyInstruction::FailCommand { sequence, error_code } => {
+                msg!("Instruction: FailCommand");
+                Self::process_fail_command(accounts, program_id, sequence, error_code)
+            }
         }
     }
     
@@ -117,37 +319,56 @@ impl Processor {
         program_id: &Pubkey,
         robot_id: [u8; 32],
         merkle_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
         metadata_uri: String,
+        device_key: Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
         let owner_account = next_account_info(account_info_iter)?;
         let authority_account = next_account_info(account_info_iter)?;
         let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
         
-        if robot_account.owner != program_id {
+        if !owner_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !owner_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
+        if metadata_uri.len() > Robot::MAX_METADATA_URI_LEN {
+            return Err(RobyError::InvalidInstruction.into());
         }
         
-        let rent = Rent::from_account_info(rent_sysvar)?;
-        if !rent.is_exempt(robot_account.lamports(), robot_account.data_len()) {
-            return Err(ProgramError::AccountNotRentExempt);
+        let (robot_address, bump) = Robot::find_address(program_id, owner_account.key, &robot_id);
+        if robot_address != *robot_account.key {
+            return Err(ProgramError::InvalidSeeds);
         }
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.is_initialized() {
+        if robot_account.owner == program_id {
             return Err(RobyError::AlreadyInitialized.into());
         }
         
-        robot_data = Robot::new(
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            owner_account,
+            robot_account,
+            system_program_account,
+            program_id,
+            &rent,
+            Robot::LEN,
+            &[Robot::SEED_PREFIX, owner_account.key.as_ref(), &robot_id, &[bump]],
+        )?;
+        
+        let robot_data = Robot::new(
             *owner_account.key,
             *authority_account.key,
+            device_key,
             robot_id,
             merkle_root,
+            tree_scheme,
             metadata_uri,
         );
         
@@ -157,6 +378,10 @@ impl Processor {
         Ok(())
     }
     
+    /// Accounts: credential, robot, issuer, payer, recipient, rent, system
+    /// program, then the credential tree when `append_to_tree` is set, then
+    /// the admin credential chain or multisig signers for the issuer.
+    #[allow(clippy::too_many_arguments)]
     fn process_issue_credential(
         accounts: &[AccountInfo],
         program_id: &Pubkey,
@@ -164,36 +389,92 @@ impl Processor {
         valid_from: i64,
         valid_until: i64,
         credential_hash: [u8; 32],
+        scope: CredentialScope,
+        append_to_tree: bool,
+        admin_proof: Option<CredentialProof>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let credential_account = next_account_info(account_info_iter)?;
         let robot_account = next_account_info(account_info_iter)?;
         let issuer_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
         let recipient_account = next_account_info(account_info_iter)?;
         let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let tree_account = if append_to_tree {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            None
+        };
         
-        if credential_account.owner != program_id || robot_account.owner != program_id {
+        if robot_account.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !issuer_account.is_signer {
+        if !payer_account.is_signer {
             return Err(RobyError::NotAuthorized.into());
         }
         
-        let robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if !robot_data.is_initialized() {
-            return Err(RobyError::UninitializedAccount.into());
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !scope.is_valid() {
+            return Err(RobyError::InvalidInstruction.into());
         }
         
-        if robot_data.authority != *issuer_account.key && robot_data.owner != *issuer_account.key {
-            return Err(RobyError::NotAuthorized.into());
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let expected = if robot_data.owner == *issuer_account.key {
+            robot_data.owner
+        } else {
+            robot_data.authority
+        };
+        let issuer_level = Self::validate_admin(
+            program_id,
+            robot_account.key,
+            &robot_data,
+            &expected,
+            issuer_account,
+            account_info_iter,
+            admin_proof.as_ref(),
+        )?;
+        
+        // Credential-based issuers can only mint levels below their own.
+        if admin_proof.is_some() && permission_level >= issuer_level {
+            return Err(RobyError::InsufficientPermissionLevel.into());
         }
         
-        let rent = Rent::from_account_info(rent_sysvar)?;
-        if !rent.is_exempt(credential_account.lamports(), credential_account.data_len()) {
-            return Err(ProgramError::AccountNotRentExempt);
+        let (credential_address, bump) = Credential::find_address(
+            program_id,
+            robot_account.key,
+            recipient_account.key,
+            &credential_hash,
+        );
+        if credential_address != *credential_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if credential_account.owner == program_id {
+            return Err(RobyError::AlreadyInitialized.into());
         }
         
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            payer_account,
+            credential_account,
+            system_program_account,
+            program_id,
+            &rent,
+            Credential::LEN,
+            &[
+                Credential::SEED_PREFIX,
+                robot_account.key.as_ref(),
+                recipient_account.key.as_ref(),
+                &credential_hash,
+                &[bump],
+            ],
+        )?;
+        
         let credential = Credential::new(
             *recipient_account.key,
             *robot_account.key,
@@ -202,10 +483,29 @@ impl Processor {
             valid_until,
             credential_hash,
             *issuer_account.key,
+            *payer_account.key,
+            scope,
         );
         
         credential.serialize(&mut &mut credential_account.data.borrow_mut()[..])?;
         
+        robot_data.open_credentials = robot_data
+            .open_credentials
+            .checked_add(1)
+            .ok_or(RobyError::ArithmeticOverflow)?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        if let Some(tree_account) = tree_account {
+            let mut tree_data = Self::load_credential_tree(program_id, robot_account.key, tree_account)?;
+            let leaf_index = tree_data
+                .tree
+                .append(credential.merkle_leaf())
+                .ok_or(RobyError::CredentialTreeFull)?;
+            tree_data.serialize(&mut &mut tree_account.data.borrow_mut()[..])?;
+            
+            msg!("Credential leaf appended at index {}", leaf_index);
+        }
+        
         msg!("Credential issued to: {}", recipient_account.key);
         Ok(())
     }
@@ -213,6 +513,7 @@ impl Processor {
     fn process_revoke_credential(
         accounts: &[AccountInfo],
         program_id: &Pubkey,
+        admin_proof: Option<CredentialProof>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let credential_account = next_account_info(account_info_iter)?;
@@ -223,18 +524,37 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        let robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *authority_account.key && robot_data.owner != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let mut credential_data = Credential::unpack(&credential_account.data.borrow())?;
+        if credential_data.robot != *robot_account.key {
+            return Err(RobyError::InvalidCredential.into());
         }
         
-        let mut credential_data = Credential::try_from_slice(&credential_account.data.borrow())?;
-        if !credential_data.is_initialized() {
-            return Err(RobyError::UninitializedAccount.into());
+        // Delegators can always revoke what they delegated.
+        let is_delegator = credential_data.parent_credential.is_some()
+            && credential_data.issuer == *authority_account.key
+            && authority_account.is_signer;
+        
+        if !is_delegator {
+            let expected = if robot_data.owner == *authority_account.key {
+                robot_data.owner
+            } else {
+                robot_data.authority
+            };
+            let admin_level = Self::validate_admin(
+                program_id,
+                robot_account.key,
+                &robot_data,
+                &expected,
+                authority_account,
+                account_info_iter,
+                admin_proof.as_ref(),
+            )?;
+            
+            // Credential-based administrators only revoke below their own level.
+            if admin_proof.is_some() && credential_data.permission_level >= admin_level {
+                return Err(RobyError::InsufficientPermissionLevel.into());
+            }
         }
         
         credential_data.revoked = true;
@@ -249,7 +569,8 @@ impl Processor {
         program_id: &Pubkey,
         command_type: CommandType,
         parameters: Vec<u8>,
-        merkle_proof: Vec<[u8; 32]>,
+        proof: CredentialProof,
+        expected_sequence: u64,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
@@ -257,6 +578,7 @@ impl Processor {
         let credential_account = next_account_info(account_info_iter)?;
         let command_log_account = next_account_info(account_info_iter)?;
         let clock_sysvar = next_account_info(account_info_iter)?;
+        let command_queue_account = next_account_info(account_info_iter)?;
         
         if robot_account.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
@@ -266,70 +588,131 @@ impl Processor {
             return Err(RobyError::NotAuthorized.into());
         }
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if !robot_data.is_initialized() {
-            return Err(RobyError::UninitializedAccount.into());
+        if command_log_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if command_log_account.data_len() < CommandLog::LEN {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if command_log_account.data.borrow()[..DISCRIMINATOR_LEN] != [0u8; DISCRIMINATOR_LEN] {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        if parameters.len() > CommandLog::MAX_PARAMETERS_LEN {
+            return Err(RobyError::InvalidInstruction.into());
         }
         
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
         if robot_data.emergency_stop {
             return Err(RobyError::RobotNotActive.into());
         }
         
-        let credential_data = Credential::try_from_slice(&credential_account.data.borrow())?;
-        if !credential_data.is_initialized() {
-            return Err(RobyError::InvalidCredential.into());
+        let clock = Clock::from_account_info(clock_sysvar)?;
+        if robot_data.is_offline(clock.unix_timestamp) {
+            return Err(RobyError::RobotOffline.into());
         }
         
-        let clock = Clock::from_account_info(clock_sysvar)?;
-        if !credential_data.is_valid(clock.unix_timestamp) {
-            return Err(RobyError::InvalidCredential.into());
+        if !robot_data.status.is_operational() {
+            return Err(RobyError::RobotNotActive.into());
         }
         
-        if credential_data.owner != *executor_account.key {
+        // Rejects replays and commands built against a stale view of the robot.
+        if expected_sequence != robot_data.command_sequence {
+            return Err(RobyError::InvalidCommandSequence.into());
+        }
+        
+        let is_listed_operator = robot_data.active_operators.contains(executor_account.key);
+        let requires_listing = matches!(
+            robot_data.operator_policy,
+            OperatorPolicy::OperatorListOnly | OperatorPolicy::CredentialAndOperatorList
+        );
+        if requires_listing && !is_listed_operator {
             return Err(RobyError::PermissionDenied.into());
         }
         
-        let proof = MerkleProof {
-            proof: merkle_proof,
-            leaf: credential_data.credential_hash,
+        // Under `OperatorListOnly` the credential slot is not read and listed
+        // operators act with `Operator` permissions.
+        let permission_level = if robot_data.operator_policy == OperatorPolicy::OperatorListOnly {
+            PermissionLevel::Operator
+        } else {
+            let chain = Self::load_credential_chain(
+                program_id,
+                robot_account.key,
+                executor_account.key,
+                credential_account,
+                account_info_iter,
+                clock.unix_timestamp,
+            )?;
+            let credential_tree = account_info_iter
+                .next()
+                .map(|tree_account| Self::load_credential_tree(program_id, robot_account.key, tree_account))
+                .transpose()?;
+            let root_credential = chain.last().ok_or(RobyError::InvalidCredential)?;
+            Self::verify_root_credential(
+                &robot_data,
+                credential_tree.as_ref(),
+                root_credential,
+                &proof,
+                clock.unix_timestamp,
+            )?;
+            
+            for credential in &chain {
+                credential
+                    .scope
+                    .authorize(command_type, &parameters, clock.unix_timestamp)?;
+            }
+            
+            chain[0].permission_level
         };
         
-        if !proof.verify(&robot_data.merkle_root) {
-            return Err(RobyError::InvalidMerkleProof.into());
-        }
-        
-        match credential_data.permission_level {
+        match permission_level {
             PermissionLevel::None | PermissionLevel::Observer => {
                 return Err(RobyError::PermissionDenied.into());
             }
             _ => {}
         }
         
-        robot_data.status = RobotStatus::Executing;
+        if permission_level < robot_data.required_permission(command_type) {
+            return Err(RobyError::InsufficientPermissionLevel.into());
+        }
+        
+        let mut queue_data =
+            Self::load_command_queue(program_id, robot_account.key, command_queue_account)?;
+        queue_data.enqueue(QueuedCommand {
+            sequence: expected_sequence,
+            command_log: *command_log_account.key,
+            status: CommandStatus::Queued,
+            enqueued_at: clock.unix_timestamp,
+            updated_at: clock.unix_timestamp,
+        })?;
+        queue_data.serialize(&mut &mut command_queue_account.data.borrow_mut()[..])?;
+        
         robot_data.last_command_timestamp = clock.unix_timestamp;
         robot_data.total_commands_executed = robot_data
             .total_commands_executed
             .checked_add(1)
             .ok_or(RobyError::ArithmeticOverflow)?;
+        robot_data.command_sequence = robot_data
+            .command_sequence
+            .checked_add(1)
+            .ok_or(RobyError::ArithmeticOverflow)?;
         
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
-        let command_log = CommandLog {
-            is_initialized: true,
-            robot: *robot_account.key,
-            executor: *executor_account.key,
+        let command_log = CommandLog::new(
+            *robot_account.key,
+            *executor_account.key,
             command_type,
-            timestamp: clock.unix_timestamp,
+            clock.unix_timestamp,
+            expected_sequence,
             parameters,
-            success: true,
-            error_code: 0,
-        };
+        );
         
-        if command_log_account.data_len() >= CommandLog::LEN {
-            command_log.serialize(&mut &mut command_log_account.data.borrow_mut()[..])?;
-        }
+        command_log.serialize(&mut &mut command_log_account.data.borrow_mut()[..])?;
         
-        msg!("Command executed successfully");
+        msg!("Command {} queued", expected_sequence);
         Ok(())
     }
     
@@ -337,29 +720,27 @@ impl Processor {
         accounts: &[AccountInfo],
         program_id: &Pubkey,
         new_merkle_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
         let authority_account = next_account_info(account_info_iter)?;
+        let clock_sysvar = next_account_info(account_info_iter)?;
         
         if robot_account.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if !robot_data.is_initialized() {
-            return Err(RobyError::UninitializedAccount.into());
-        }
-        
-        if robot_data.authority != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
-        }
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
         
-        robot_data.merkle_root = new_merkle_root;
+        let clock = Clock::from_account_info(clock_sysvar)?;
+        robot_data.rotate_root(new_merkle_root, tree_scheme, clock.slot, clock.unix_timestamp);
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
         msg!("Merkle root updated");
@@ -379,19 +760,18 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !current_authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *current_authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
-        }
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            current_authority_account,
+            account_info_iter.as_slice(),
+        )?;
         
-        robot_data.authority = new_authority;
+        robot_data.pending_authority = Some(new_authority);
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
-        msg!("Authority transferred to: {}", new_authority);
+        msg!("Authority transfer to {} pending acceptance", new_authority);
         Ok(())
     }
     
@@ -399,6 +779,7 @@ impl Processor {
         accounts: &[AccountInfo],
         program_id: &Pubkey,
         operator: Pubkey,
+        admin_proof: Option<CredentialProof>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
@@ -408,14 +789,16 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
-        }
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_admin(
+            program_id,
+            robot_account.key,
+            &robot_data,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter,
+            admin_proof.as_ref(),
+        )?;
         
         if robot_data.active_operators.len() >= robot_data.max_operators as usize {
             return Err(RobyError::InvalidRobotState.into());
@@ -435,6 +818,7 @@ impl Processor {
         accounts: &[AccountInfo],
         program_id: &Pubkey,
         operator: Pubkey,
+        admin_proof: Option<CredentialProof>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
@@ -444,25 +828,67 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_admin(
+            program_id,
+            robot_account.key,
+            &robot_data,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter,
+            admin_proof.as_ref(),
+        )?;
+        
+        robot_data.active_operators.retain(|&x| x != operator);
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Operator removed: {}", operator);
+        Ok(())
+    }
+    
+    fn process_add_guardian(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        guardian: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if robot_data.emergency_stop {
+            return Err(RobyError::ResumePolicyLocked.into());
+        }
+        
+        if robot_data.guardians.len() >= Robot::MAX_GUARDIANS {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        if !robot_data.guardians.contains(&guardian) {
+            robot_data.guardians.push(guardian);
         }
         
-        robot_data.active_operators.retain(|&x| x != operator);
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
-        msg!("Operator removed: {}", operator);
+        msg!("Guardian added: {}", guardian);
         Ok(())
     }
     
-    fn process_emergency_stop(
+    fn process_remove_guardian(
         accounts: &[AccountInfo],
         program_id: &Pubkey,
+        guardian: Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
@@ -472,17 +898,59 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if robot_data.emergency_stop {
+            return Err(RobyError::ResumePolicyLocked.into());
+        }
+        
+        robot_data.guardians.retain(|&x| x != guardian);
+        if robot_data.resume_threshold as usize > robot_data.resume_approver_count() {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Guardian removed: {}", guardian);
+        Ok(())
+    }
+    
+    fn process_emergency_stop(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        reason_code: u16,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let stopper_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !stopper_account.is_signer {
             return Err(RobyError::NotAuthorized.into());
         }
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if !robot_data.can_emergency_stop(stopper_account.key) {
+            return Err(RobyError::NotAuthorized.into());
+        }
         
         robot_data.emergency_stop = true;
-        robot_data.status = RobotStatus::Error;
+        robot_data.emergency_stop_reason = reason_code;
+        robot_data.emergency_stopped_by = *stopper_account.key;
+        robot_data.emergency_stopped_at = Clock::get()?.unix_timestamp;
+        Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Error)?;
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
-        msg!("Emergency stop activated");
+        msg!("Emergency stop activated by {} (reason {})", stopper_account.key, reason_code);
         Ok(())
     }
     
@@ -498,17 +966,48 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
+        // With a resume policy in place the authority must present a live
+        // proposal with enough approvals, followed by its payer to refund.
+        let proposal_accounts = if robot_data.emergency_stop && robot_data.resume_threshold > 0 {
+            Some((next_account_info(account_info_iter)?, next_account_info(account_info_iter)?))
+        } else {
+            None
+        };
+        
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if let Some((proposal_account, payer_account)) = proposal_accounts {
+            if proposal_account.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            
+            let proposal_data = ResumeProposal::unpack(&proposal_account.data.borrow())?;
+            if proposal_data.robot != *robot_account.key
+                || proposal_data.payer != *payer_account.key
+            {
+                return Err(RobyError::InvalidAccountData.into());
+            }
+            
+            if !proposal_data.is_live(&robot_data, Clock::get()?.unix_timestamp) {
+                return Err(RobyError::ProposalExpired.into());
+            }
+            
+            if proposal_data.approval_count(&robot_data) < robot_data.resume_threshold as usize {
+                return Err(RobyError::ResumeNotApproved.into());
+            }
+            
+            close_account(proposal_account, payer_account)?;
         }
         
         robot_data.emergency_stop = false;
-        robot_data.status = RobotStatus::Idle;
+        Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Idle)?;
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
         msg!("Robot resumed");
@@ -519,6 +1018,7 @@ impl Processor {
         accounts: &[AccountInfo],
         program_id: &Pubkey,
         status: u8,
+        admin_proof: Option<CredentialProof>,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let robot_account = next_account_info(account_info_iter)?;
@@ -528,24 +1028,27 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !authority_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.authority != *authority_account.key {
-            return Err(RobyError::NotAuthorized.into());
-        }
-        
-        robot_data.status = match status {
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_admin(
+            program_id,
+            robot_account.key,
+            &robot_data,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter,
+            admin_proof.as_ref(),
+        )?;
+        
+        let status = match status {
             0 => RobotStatus::Offline,
             1 => RobotStatus::Idle,
             2 => RobotStatus::Active,
-            3 => RobotStatus::Executing,
             4 => RobotStatus::Error,
             5 => RobotStatus::Maintenance,
+            // `Executing` is only entered through command acknowledgements.
             _ => return Err(RobyError::InvalidRobotState.into()),
         };
+        Self::set_status(robot_account.key, &mut robot_data, status)?;
         
         robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
         
@@ -566,22 +1069,2958 @@ impl Processor {
             return Err(ProgramError::IncorrectProgramId);
         }
         
-        if !current_owner_account.is_signer {
-            return Err(RobyError::NotAuthorized.into());
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.owner,
+            current_owner_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.pending_owner = Some(new_owner);
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Ownership transfer to {} pending acceptance", new_owner);
+        Ok(())
+    }
+    
+    fn process_close_robot(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let owner_account = next_account_info(account_info_iter)?;
+        let receiver_account = next_account_info(account_info_iter)?;
+        let command_queue_account = next_account_info(account_info_iter)?;
+        let credential_tree_account = next_account_info(account_info_iter)?;
+        let resume_proposal_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
         
-        let mut robot_data = Robot::try_from_slice(&robot_account.data.borrow())?;
-        if robot_data.owner != *current_owner_account.key {
-            return Err(RobyError::NotAuthorized.into());
+        // A resume proposal is followed by its payer to refund.
+        let resume_payer_account = if resume_proposal_account.owner == program_id {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            None
+        };
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.owner,
+            owner_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if robot_data.open_credentials > 0 || robot_data.open_proposals > 0 {
+            return Err(RobyError::DependentAccountsOpen.into());
         }
         
-        robot_data.owner = new_owner;
-        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        // The queue, tree and resume proposal are keyed by the robot's
+        // address alone, so they are closed with it rather than left for a
+        // re-initialized robot.
+        let (queue_address, _) = CommandQueue::find_address(program_id, robot_account.key);
+        let (tree_address, _) = CredentialTree::find_address(program_id, robot_account.key);
+        let (resume_proposal_address, _) = ResumeProposal::find_address(program_id, robot_account.key);
+        if *command_queue_account.key != queue_address
+            || *credential_tree_account.key != tree_address
+            || *resume_proposal_account.key != resume_proposal_address
+        {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if let Some(resume_payer_account) = resume_payer_account {
+            let proposal_data = ResumeProposal::unpack(&resume_proposal_account.data.borrow())?;
+            if proposal_data.payer != *resume_payer_account.key {
+                return Err(RobyError::InvalidAccountData.into());
+            }
+            close_account(resume_proposal_account, resume_payer_account)?;
+        }
+        
+        for dependent_account in [command_queue_account, credential_tree_account] {
+            if dependent_account.owner == program_id {
+                close_account(dependent_account, receiver_account)?;
+            }
+        }
+        
+        close_account(robot_account, receiver_account)?;
         
-        msg!("Ownership transferred to: {}", new_owner);
+        msg!("Robot closed, rent refunded to: {}", receiver_account.key);
         Ok(())
     }
-}
+    
+    fn process_close_credential(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let credential_account = next_account_info(account_info_iter)?;
+        let receiver_account = next_account_info(account_info_iter)?;
+        let closer_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let clock_sysvar = next_account_info(account_info_iter)?;
+        
+        if credential_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let credential_data = Credential::unpack(&credential_account.data.borrow())?;
+        if *robot_account.key != credential_data.robot {
+            return Err(RobyError::InvalidCredential.into());
+        }
+        
+        let signer_accounts = account_info_iter.as_slice();
+        let is_issuer = Self::validate_authority(
+            program_id,
+            &credential_data.issuer,
+            closer_account,
+            signer_accounts,
+        )
+        .is_ok();
+        let is_authority = robot_account.owner == program_id
+            && Self::validate_authority(
+                program_id,
+                &Robot::unpack(&robot_account.data.borrow())?.authority,
+                closer_account,
+                signer_accounts,
+            )
+            .is_ok();
+        
+        let clock = Clock::from_account_info(clock_sysvar)?;
+        if !is_issuer && !is_authority {
+            // Expired credentials can be cleaned up by anyone, but the rent
+            // then goes back to whoever paid for the account.
+            if clock.unix_timestamp <= credential_data.valid_until
+                || *receiver_account.key != credential_data.payer
+            {
+                return Err(RobyError::NotAuthorized.into());
+            }
+        }
+        
+        // Legacy robots were never counted and may already be gone.
+        if robot_account.owner == program_id {
+            let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+            robot_data.open_credentials = robot_data.open_credentials.saturating_sub(1);
+            robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        }
+        
+        close_account(credential_account, receiver_account)?;
+        
+        msg!("Credential closed, rent refunded to: {}", receiver_account.key);
+        Ok(())
+    }
+    
+    fn process_close_command_log(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let command_log_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let receiver_account = next_account_info(account_info_iter)?;
+        
+        if command_log_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let command_log = CommandLog::unpack(&command_log_account.data.borrow())?;
+        if command_log.robot != *robot_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if robot_account.owner == program_id {
+            let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+            Self::validate_authority(
+                program_id,
+                &robot_data.authority,
+                authority_account,
+                account_info_iter.as_slice(),
+            )?;
+            
+            // The queue needs the log until the command is acknowledged or failed.
+            if !command_log.status.is_finished() {
+                return Err(RobyError::InvalidCommandStatus.into());
+            }
+        } else if *receiver_account.key != command_log.executor {
+            // Once the robot and its queue are closed anyone may clean up its
+            // logs, refunding the executor who created them.
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        close_account(command_log_account, receiver_account)?;
+        
+        msg!("Command log closed, rent refunded to: {}", receiver_account.key);
+        Ok(())
+    }
+    
+    fn process_migrate_account(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        account_type: AccountType,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let target_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let signer_account = next_account_info(account_info_iter)?;
+        
+        if target_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !payer_account.is_signer || !signer_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let (migrated, new_len) = {
+            let data = target_account.data.borrow();
+            if data.len() < DISCRIMINATOR_LEN {
+                return Err(RobyError::InvalidAccountData.into());
+            }
+            
+            let header = &data[..DISCRIMINATOR_LEN];
+            let current = match account_type {
+                AccountType::Robot => header == Robot::DISCRIMINATOR,
+                AccountType::Credential => header == Credential::DISCRIMINATOR,
+                AccountType::CommandLog => header == CommandLog::DISCRIMINATOR,
+            };
+            if current {
+                msg!("Account already uses the current layout");
+                return Ok(());
+            }
+            
+            // Legacy accounts live at arbitrary addresses, so the stated type
+            // is checked against the exact layout and a key that controlled
+            // the account under it must sign.
+            match account_type {
+                AccountType::Robot => {
+                    let legacy = unpack_legacy::<LegacyRobot>(&data, LegacyRobot::LEN)?;
+                    if !legacy.is_initialized {
+                        return Err(RobyError::UninitializedAccount.into());
+                    }
+                    if legacy.owner != *signer_account.key && legacy.authority != *signer_account.key {
+                        return Err(RobyError::NotAuthorized.into());
+                    }
+                    (Robot::from(legacy).try_to_vec()?, Robot::LEN)
+                }
+                AccountType::Credential => {
+                    let legacy = unpack_legacy::<LegacyCredential>(&data, LegacyCredential::LEN)?;
+                    if !legacy.is_initialized {
+                        return Err(RobyError::UninitializedAccount.into());
+                    }
+                    if legacy.issuer != *signer_account.key && legacy.owner != *signer_account.key {
+                        return Err(RobyError::NotAuthorized.into());
+                    }
+                    (Credential::from(legacy).try_to_vec()?, Credential::LEN)
+                }
+                AccountType::CommandLog => {
+                    let legacy = unpack_legacy::<LegacyCommandLog>(&data, LegacyCommandLog::LEN)?;
+                    if !legacy.is_initialized {
+                        return Err(RobyError::UninitializedAccount.into());
+                    }
+                    // The executor, or the authority of the already migrated
+                    // robot passed next.
+                    if legacy.executor != *signer_account.key {
+                        let robot_account = next_account_info(account_info_iter)?;
+                        if robot_account.owner != program_id || *robot_account.key != legacy.robot {
+                            return Err(RobyError::InvalidAccountData.into());
+                        }
+                        if Robot::unpack(&robot_account.data.borrow())?.authority != *signer_account.key {
+                            return Err(RobyError::NotAuthorized.into());
+                        }
+                    }
+                    (CommandLog::from(legacy).try_to_vec()?, CommandLog::LEN)
+                }
+            }
+        };
+        
+        if target_account.data_len() < new_len {
+            let rent = Rent::get()?;
+            let top_up = rent
+                .minimum_balance(new_len)
+                .saturating_sub(target_account.lamports());
+            if top_up > 0 {
+                invoke(
+                    &system_instruction::transfer(payer_account.key, target_account.key, top_up),
+                    &[
+                        payer_account.clone(),
+                        target_account.clone(),
+                        system_program_account.clone(),
+                    ],
+                )?;
+            }
+            target_account.realloc(new_len, true)?;
+        }
+        
+        let mut data = target_account.data.borrow_mut();
+        data.fill(0);
+        data[..migrated.len()].copy_from_slice(&migrated);
+        
+        msg!("Account migrated to layout version {}", data[DISCRIMINATOR_LEN]);
+        Ok(())
+    }
+    
+    fn process_verify_credentials(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        multiproof: CredentialMultiProof,
+        revocation_proof: SparseMerkleMultiProof,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let clock_sysvar = next_account_info(account_info_iter)?;
+        let credential_tree_account = match multiproof {
+            CredentialMultiProof::Positional(_) => Some(next_account_info(account_info_iter)?),
+            CredentialMultiProof::SortedPair(_) => None,
+        };
+        let credential_accounts = account_info_iter.as_slice();
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if credential_accounts.is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        
+        let clock = Clock::from_account_info(clock_sysvar)?;
+        
+        // Credential accounts must be passed in the same order as their leaves
+        // appear in the tree.
+        let mut leaves = Vec::with_capacity(credential_accounts.len());
+        for credential_account in credential_accounts {
+            if credential_account.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            
+            let credential_data = Credential::unpack(&credential_account.data.borrow())?;
+            if credential_data.robot != *robot_account.key
+                || !credential_data.is_valid(clock.unix_timestamp)
+            {
+                return Err(RobyError::InvalidCredential.into());
+            }
+            
+            leaves.push(credential_data.merkle_leaf());
+        }
+        
+        if !revocation_proof.verify_non_membership(&robot_data.revocation_root, &leaves) {
+            return Err(RobyError::CredentialRevoked.into());
+        }
+        
+        let verified = match &multiproof {
+            CredentialMultiProof::SortedPair(proof) => {
+                let mut sorted_pair_roots = robot_data
+                    .accepted_roots(clock.unix_timestamp)
+                    .filter(|(_, scheme)| *scheme == MerkleTreeScheme::SortedPair)
+                    .peekable();
+                if sorted_pair_roots.peek().is_none() {
+                    return Err(RobyError::UnsupportedTreeScheme.into());
+                }
+                
+                sorted_pair_roots.any(|(root, _)| proof.verify(&root, &leaves))
+            }
+            CredentialMultiProof::Positional(proof) => {
+                let root = proof
+                    .compute_root(&leaves)
+                    .ok_or(RobyError::InvalidMerkleProof)?;
+                
+                // The tree address is checked even when it is not initialized
+                // so a look-alike account can never supply roots.
+                let tree_account = credential_tree_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                let (tree_address, _) = CredentialTree::find_address(program_id, robot_account.key);
+                if *tree_account.key != tree_address {
+                    return Err(ProgramError::InvalidSeeds);
+                }
+                
+                let matches_tree = tree_account.owner == program_id
+                    && Self::load_credential_tree(program_id, robot_account.key, tree_account)?
+                        .tree
+                        .is_known_root(&root);
+                
+                matches_tree
+                    || robot_data.accepted_roots(clock.unix_timestamp).any(|(accepted, scheme)| {
+                        scheme == MerkleTreeScheme::Positional && accepted == root
+                    })
+            }
+        };
+        
+        if !verified {
+            return Err(RobyError::InvalidMerkleProof.into());
+        }
+        
+        msg!("Verified {} credentials", leaves.len());
+        Ok(())
+    }
+    
+    fn process_initialize_credential_tree(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        depth: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let tree_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !payer_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let tree = IncrementalMerkleTree::new(depth).ok_or(RobyError::InvalidInstruction)?;
+        
+        let (tree_address, bump) = CredentialTree::find_address(program_id, robot_account.key);
+        if tree_address != *tree_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if tree_account.owner == program_id {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            payer_account,
+            tree_account,
+            system_program_account,
+            program_id,
+            &rent,
+            CredentialTree::LEN,
+            &[CredentialTree::SEED_PREFIX, robot_account.key.as_ref(), &[bump]],
+        )?;
+        
+        let tree_data = CredentialTree::new(*robot_account.key, tree);
+        tree_data.serialize(&mut &mut tree_account.data.borrow_mut()[..])?;
+        
+        msg!("Credential tree initialized with depth {}", depth);
+        Ok(())
+    }
+    
+    fn process_update_revocation_root(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        new_revocation_root: [u8; 32],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.revocation_root = new_revocation_root;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Revocation root updated");
+        Ok(())
+    }
+    
+    fn process_set_root_grace_period(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        grace_period: i64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if grace_period < 0 {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.root_grace_period = grace_period;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Root grace period set to {}s", grace_period);
+        Ok(())
+    }
+    
+    fn process_initialize_command_queue(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let queue_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !payer_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let (queue_address, bump) = CommandQueue::find_address(program_id, robot_account.key);
+        if queue_address != *queue_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if queue_account.owner == program_id {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            payer_account,
+            queue_account,
+            system_program_account,
+            program_id,
+            &rent,
+            CommandQueue::LEN,
+            &[CommandQueue::SEED_PREFIX, robot_account.key.as_ref(), &[bump]],
+        )?;
+        
+        let queue_data = CommandQueue::new(*robot_account.key);
+        queue_data.serialize(&mut &mut queue_account.data.borrow_mut()[..])?;
+        
+        msg!("Command queue initialized");
+        Ok(())
+    }
+    
+    fn process_acknowledge_command(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        sequence: u64,
+        status: CommandStatus,
+        error_code: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let device_account = next_account_info(account_info_iter)?;
+        let command_queue_account = next_account_info(account_info_iter)?;
+        let command_log_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || command_log_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !device_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if robot_data.device_key != *device_account.key {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        let mut queue_data =
+            Self::load_command_queue(program_id, robot_account.key, command_queue_account)?;
+        let clock = Clock::get()?;
+        
+        let entry = queue_data
+            .get_mut(sequence)
+            .ok_or(RobyError::InvalidCommandSequence)?;
+        if entry.command_log != *command_log_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        if !entry.status.can_transition_to(status) {
+            return Err(RobyError::InvalidCommandStatus.into());
+        }
+        entry.status = status;
+        entry.updated_at = clock.unix_timestamp;
+        
+        let mut command_log = CommandLog::unpack(&command_log_account.data.borrow())?;
+        command_log.status = status;
+        command_log.success = status == CommandStatus::Completed;
+        command_log.error_code = if status == CommandStatus::Failed { error_code } else { 0 };
+        command_log.serialize(&mut &mut command_log_account.data.borrow_mut()[..])?;
+        
+        if status == CommandStatus::Started {
+            Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Executing)?;
+        } else if robot_data.status == RobotStatus::Executing && !queue_data.has_started_commands() {
+            Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Idle)?;
+        }
+        
+        queue_data.serialize(&mut &mut command_queue_account.data.borrow_mut()[..])?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Command {} acknowledged: {:?}", sequence, status);
+        Ok(())
+    }
+    
+    /// Lets the authority fail a queued or started command the robot never
+    /// finished, so its queue slot can be reused.
+    fn process_fail_command(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        sequence: u64,
+        error_code: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let command_queue_account = next_account_info(account_info_iter)?;
+        let command_log_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || command_log_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let mut queue_data =
+            Self::load_command_queue(program_id, robot_account.key, command_queue_account)?;
+        let clock = Clock::get()?;
+        
+        let entry = queue_data
+            .get_mut(sequence)
+            .ok_or(RobyError::InvalidCommandSequence)?;
+        if entry.command_log != *command_log_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        if entry.status.is_finished() {
+            return Err(RobyError::InvalidCommandStatus.into());
+        }
+        entry.status = CommandStatus::Failed;
+        entry.updated_at = clock.unix_timestamp;
+        
+        let mut command_log = CommandLog::unpack(&command_log_account.data.borrow())?;
+        command_log.status = CommandStatus::Failed;
+        command_log.success = false;
+        command_log.error_code = error_code;
+        command_log.serialize(&mut &mut command_log_account.data.borrow_mut()[..])?;
+        
+        if robot_data.status == RobotStatus::Executing && !queue_data.has_started_commands() {
+            Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Idle)?;
+        }
+        
+        queue_data.serialize(&mut &mut command_queue_account.data.borrow_mut()[..])?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Command {} failed by authority", sequence);
+        Ok(())
+    }
+    
+    /// Rotates the key the robot signs with. With `co_signed` the previous
+    /// device key follows the authority and must sign the handover too.
+    fn process_rotate_device_key(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        new_device_key: Pubkey,
+        co_signed: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        let old_device_account = if co_signed {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            None
+        };
+        let signer_accounts = account_info_iter.as_slice();
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(program_id, &robot_data.authority, authority_account, signer_accounts)?;
+        
+        if let Some(old_device_account) = old_device_account {
+            if !old_device_account.is_signer || *old_device_account.key != robot_data.device_key {
+                return Err(RobyError::NotAuthorized.into());
+            }
+        }
+        
+        robot_data.device_key = new_device_key;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Device key rotated to: {}", new_device_key);
+        Ok(())
+    }
+    
+    /// Status reported by the robot itself. `Executing` is only entered
+    /// through `AcknowledgeCommand`.
+    fn process_report_status(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        status: RobotStatus,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let device_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !device_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if robot_data.device_key != *device_account.key {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if status == RobotStatus::Executing {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        Self::set_status(robot_account.key, &mut robot_data, status)?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Robot reported status: {:?}", robot_data.status);
+        Ok(())
+    }
+    
+    fn process_heartbeat(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        battery_level: Option<u8>,
+        health_flags: Option<u32>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let device_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !device_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if robot_data.device_key != *device_account.key {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if battery_level.is_some_and(|level| level > 100) {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        robot_data.last_heartbeat = Clock::get()?.unix_timestamp;
+        if battery_level.is_some() {
+            robot_data.battery_level = battery_level;
+        }
+        if health_flags.is_some() {
+            robot_data.health_flags = health_flags;
+        }
+        // A heartbeat never fails on status: a stopped robot stays stopped.
+        if robot_data.status == RobotStatus::Offline && !robot_data.emergency_stop {
+            Self::set_status(robot_account.key, &mut robot_data, RobotStatus::Idle)?;
+        }
+        
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Heartbeat recorded");
+        Ok(())
+    }
+    
+    fn process_set_liveness_timeout(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        liveness_timeout: i64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if liveness_timeout < 0 {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.liveness_timeout = liveness_timeout;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Liveness timeout set to {}s", liveness_timeout);
+        Ok(())
+    }
+    
+    fn process_set_resume_policy(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        resume_threshold: u8,
+        proposal_ttl: i64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if proposal_ttl <= 0 {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if resume_threshold as usize > robot_data.resume_approver_count() {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        // While stopped the policy may only tighten, so the authority cannot
+        // lower the bar for its own resume.
+        if robot_data.emergency_stop
+            && (resume_threshold < robot_data.resume_threshold
+                || proposal_ttl > robot_data.resume_proposal_ttl)
+        {
+            return Err(RobyError::ResumePolicyLocked.into());
+        }
+        
+        robot_data.resume_threshold = resume_threshold;
+        robot_data.resume_proposal_ttl = proposal_ttl;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Resume policy set: {} approvals, {}s ttl", resume_threshold, proposal_ttl);
+        Ok(())
+    }
+    
+    fn process_propose_resume(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let proposer_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !payer_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_resume_approver(
+            program_id,
+            &robot_data,
+            proposer_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if !robot_data.emergency_stop {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        let (proposal_address, bump) = ResumeProposal::find_address(program_id, robot_account.key);
+        if proposal_address != *proposal_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        let clock = Clock::get()?;
+        
+        // A proposal left over from an earlier stop, or one that expired,
+        // is replaced in place and still refunds whoever funded it.
+        let payer = if proposal_account.owner == program_id {
+            let existing = ResumeProposal::unpack(&proposal_account.data.borrow())?;
+            if existing.is_live(&robot_data, clock.unix_timestamp) {
+                return Err(RobyError::AlreadyInitialized.into());
+            }
+            existing.payer
+        } else {
+            let rent = Rent::from_account_info(rent_sysvar)?;
+            create_pda_account(
+                payer_account,
+                proposal_account,
+                system_program_account,
+                program_id,
+                &rent,
+                ResumeProposal::LEN,
+                &[ResumeProposal::SEED_PREFIX, robot_account.key.as_ref(), &[bump]],
+            )?;
+            *payer_account.key
+        };
+        
+        let mut proposal_data = ResumeProposal::new(
+            *robot_account.key,
+            *proposer_account.key,
+            clock.unix_timestamp,
+            robot_data.resume_proposal_ttl,
+        );
+        proposal_data.payer = payer;
+        proposal_data.serialize(&mut &mut proposal_account.data.borrow_mut()[..])?;
+        
+        msg!("Resume proposed by {}", proposer_account.key);
+        Ok(())
+    }
+    
+    fn process_approve_resume(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let approver_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || proposal_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_resume_approver(
+            program_id,
+            &robot_data,
+            approver_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let mut proposal_data = ResumeProposal::unpack(&proposal_account.data.borrow())?;
+        if proposal_data.robot != *robot_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if !proposal_data.is_live(&robot_data, Clock::get()?.unix_timestamp) {
+            return Err(RobyError::ProposalExpired.into());
+        }
+        
+        if proposal_data.approvals.contains(approver_account.key) {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        if proposal_data.approvals.len() >= ResumeProposal::MAX_APPROVALS {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        proposal_data.approvals.push(*approver_account.key);
+        proposal_data.serialize(&mut &mut proposal_account.data.borrow_mut()[..])?;
+        
+        msg!(
+            "Resume approved by {} ({} approvals)",
+            approver_account.key,
+            proposal_data.approval_count(&robot_data)
+        );
+        Ok(())
+    }
+    
+    fn process_initialize_multisig(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        multisig_id: [u8; 32],
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let multisig_account = next_account_info(account_info_iter)?;
+        let creator_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if !creator_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut unique_signers = signers.clone();
+        unique_signers.sort();
+        unique_signers.dedup();
+        if unique_signers.len() != signers.len()
+            || signers.len() > MultisigAuthority::MAX_SIGNERS
+            || threshold == 0
+            || threshold as usize > signers.len()
+        {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let (multisig_address, bump) =
+            MultisigAuthority::find_address(program_id, creator_account.key, &multisig_id);
+        if multisig_address != *multisig_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if multisig_account.owner == program_id {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            creator_account,
+            multisig_account,
+            system_program_account,
+            program_id,
+            &rent,
+            MultisigAuthority::LEN,
+            &[MultisigAuthority::SEED_PREFIX, creator_account.key.as_ref(), &multisig_id, &[bump]],
+        )?;
+        
+        let multisig_data = MultisigAuthority::new(signers, threshold);
+        multisig_data.serialize(&mut &mut multisig_account.data.borrow_mut()[..])?;
+        
+        msg!("Multisig initialized: {} of {}", threshold, multisig_data.signers.len());
+        Ok(())
+    }
+    
+    fn process_configure_proposals(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+        timelock: i64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut unique_approvers = approvers.clone();
+        unique_approvers.sort();
+        unique_approvers.dedup();
+        if unique_approvers.len() != approvers.len()
+            || approvers.len() > Robot::MAX_PROPOSAL_APPROVERS
+            || threshold as usize > approvers.len()
+            || timelock < 0
+        {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.proposal_approvers = approvers;
+        robot_data.proposal_threshold = threshold;
+        robot_data.proposal_timelock = timelock;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Proposals configured: {} approvals, {}s timelock", threshold, timelock);
+        Ok(())
+    }
+    
+    fn process_create_proposal(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        instruction_data: Vec<u8>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let proposer_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !payer_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if instruction_data.len() > Proposal::MAX_INSTRUCTION_LEN
+            || !RobyInstruction::unpack(&instruction_data)?.requires_proposal()
+        {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if robot_data.proposal_threshold == 0 {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        let is_approver = robot_data.proposal_approvers.contains(proposer_account.key);
+        if !is_approver
+            && robot_data.authority != *proposer_account.key
+            && robot_data.owner != *proposer_account.key
+        {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        Self::validate_authority(
+            program_id,
+            proposer_account.key,
+            proposer_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let id = robot_data.proposal_count;
+        let (proposal_address, bump) = Proposal::find_address(program_id, robot_account.key, id);
+        if proposal_address != *proposal_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            payer_account,
+            proposal_account,
+            system_program_account,
+            program_id,
+            &rent,
+            Proposal::LEN,
+            &[Proposal::SEED_PREFIX, robot_account.key.as_ref(), &id.to_le_bytes(), &[bump]],
+        )?;
+        
+        let clock = Clock::get()?;
+        let mut proposal_data = Proposal::new(
+            *robot_account.key,
+            *proposer_account.key,
+            id,
+            instruction_data,
+            clock.unix_timestamp,
+        );
+        proposal_data.payer = *payer_account.key;
+        if is_approver {
+            proposal_data.approve(&robot_data, *proposer_account.key, clock.unix_timestamp);
+        }
+        proposal_data.serialize(&mut &mut proposal_account.data.borrow_mut()[..])?;
+        
+        robot_data.proposal_count = robot_data
+            .proposal_count
+            .checked_add(1)
+            .ok_or(RobyError::ArithmeticOverflow)?;
+        robot_data.open_proposals = robot_data
+            .open_proposals
+            .checked_add(1)
+            .ok_or(RobyError::ArithmeticOverflow)?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Proposal {} created", id);
+        Ok(())
+    }
+    
+    fn process_approve_proposal(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let approver_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || proposal_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        // The owner may always approve, which owner-gated proposals need
+        // even when it is not one of the configured approvers.
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if !robot_data.proposal_approvers.contains(approver_account.key)
+            && robot_data.owner != *approver_account.key
+        {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        Self::validate_authority(
+            program_id,
+            approver_account.key,
+            approver_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        let mut proposal_data = Proposal::unpack(&proposal_account.data.borrow())?;
+        if proposal_data.robot != *robot_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if proposal_data.approvals.contains(approver_account.key) {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        if proposal_data.approvals.len() >= Proposal::MAX_APPROVALS {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        proposal_data.approve(&robot_data, *approver_account.key, Clock::get()?.unix_timestamp);
+        proposal_data.serialize(&mut &mut proposal_account.data.borrow_mut()[..])?;
+        
+        msg!(
+            "Proposal {} approved by {} ({} approvals)",
+            proposal_data.id,
+            approver_account.key,
+            proposal_data.approval_count(&robot_data)
+        );
+        Ok(())
+    }
+    
+    /// Cancels a proposal, refunding its rent to the proposer. The
+    /// proposer, the robot's owner or its authority may cancel.
+    fn process_cancel_proposal(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let canceller_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || proposal_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let proposal_data = Proposal::unpack(&proposal_account.data.borrow())?;
+        if proposal_data.robot != *robot_account.key || proposal_data.payer != *payer_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if proposal_data.proposer != *canceller_account.key
+            && robot_data.authority != *canceller_account.key
+            && robot_data.owner != *canceller_account.key
+        {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        Self::validate_authority(
+            program_id,
+            canceller_account.key,
+            canceller_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        close_account(proposal_account, payer_account)?;
+        
+        robot_data.open_proposals = robot_data.open_proposals.saturating_sub(1);
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Proposal {} cancelled", proposal_data.id);
+        Ok(())
+    }
+    
+    /// Runs an approved proposal once its timelock has passed. The accounts
+    /// after the proposer are the proposed instruction's own accounts,
+    /// starting with the robot; the robot's owner and authority among them
+    /// are treated as having signed, the approvals standing in for them.
+    fn process_execute_proposal(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let proposal_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+        let instruction_accounts = account_info_iter.as_slice();
+        let robot_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id || proposal_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let proposal_data = Proposal::unpack(&proposal_account.data.borrow())?;
+        if proposal_data.robot != *robot_account.key || proposal_data.payer != *payer_account.key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        if !proposal_data.is_executable(&robot_data, Clock::get()?.unix_timestamp) {
+            return Err(RobyError::ProposalNotExecutable.into());
+        }
+        
+        let instruction = RobyInstruction::unpack(&proposal_data.instruction_data)?;
+        if !instruction.requires_proposal() {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let approved_signer = Self::proposal_signer(&robot_data, &proposal_data, &instruction)?;
+        
+        // Closed before dispatching so the proposal can never run twice.
+        close_account(proposal_account, payer_account)?;
+        robot_data.open_proposals = robot_data.open_proposals.saturating_sub(1);
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        let approved_accounts: Vec<AccountInfo> = instruction_accounts
+            .iter()
+            .map(|account| {
+                let mut account = account.clone();
+                if *account.key == approved_signer {
+                    account.is_signer = true;
+                }
+                account
+            })
+            .collect();
+        
+        Self::dispatch(program_id, &approved_accounts, instruction)?;
+        
+        msg!("Proposal {} executed", proposal_data.id);
+        Ok(())
+    }
+    
+    /// The key an executed proposal signs for: the one role the proposed
+    /// instruction checks. Approvers are configured by the authority, so
+    /// owner-gated instructions also need the owner's own approval.
+    fn proposal_signer(
+        robot_data: &Robot,
+        proposal_data: &Proposal,
+        instruction: &RobyInstruction,
+    ) -> Result<Pubkey, ProgramError> {
+        match instruction {
+            RobyInstruction::TransferOwnership { .. } => {
+                if !proposal_data.approvals.contains(&robot_data.owner) {
+                    return Err(RobyError::NotAuthorized.into());
+                }
+                Ok(robot_data.owner)
+            }
+            _ => Ok(robot_data.authority),
+        }
+    }
+    
+    fn process_accept_ownership(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let new_owner_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let pending_owner = robot_data.pending_owner.ok_or(RobyError::InvalidRobotState)?;
+        Self::validate_authority(
+            program_id,
+            &pending_owner,
+            new_owner_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.owner = pending_owner;
+        robot_data.pending_owner = None;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Ownership transferred to: {}", pending_owner);
+        Ok(())
+    }
+    
+    fn process_accept_authority(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let new_authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let pending_authority = robot_data.pending_authority.ok_or(RobyError::InvalidRobotState)?;
+        Self::validate_authority(
+            program_id,
+            &pending_authority,
+            new_authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.authority = pending_authority;
+        robot_data.pending_authority = None;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Authority transferred to: {}", pending_authority);
+        Ok(())
+    }
+    
+    /// Withdraws pending transfers: the owner cancels a pending ownership
+    /// transfer and the authority a pending authority transfer.
+    fn process_cancel_transfer(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let signer_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        let is_owner = robot_data.owner == *signer_account.key;
+        let is_authority = robot_data.authority == *signer_account.key;
+        if !is_owner && !is_authority {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        Self::validate_authority(
+            program_id,
+            signer_account.key,
+            signer_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        if is_owner {
+            robot_data.pending_owner = None;
+        }
+        if is_authority {
+            robot_data.pending_authority = None;
+        }
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Pending transfer cancelled");
+        Ok(())
+    }
+    
+    /// Loads the holder's credential and, for delegated credentials, each
+    /// parent from `parent_iter` up to the credential the robot issued. The
+    /// chain starts with the holder's credential. Every link must still be
+    /// valid, so revoking a credential cuts off everything delegated from it.
+    fn load_credential_chain<'a, 'b>(
+        program_id: &Pubkey,
+        robot_key: &Pubkey,
+        holder: &Pubkey,
+        credential_account: &AccountInfo<'b>,
+        parent_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+        now: UnixTimestamp,
+    ) -> Result<Vec<Credential>, ProgramError> {
+        let credential_data = Self::load_valid_credential(program_id, robot_key, credential_account, now)?;
+        if credential_data.owner != *holder {
+            return Err(RobyError::PermissionDenied.into());
+        }
+        
+        let mut chain = vec![credential_data];
+        loop {
+            let child = &chain[chain.len() - 1];
+            let Some(parent_key) = child.parent_credential else {
+                break;
+            };
+            let child_depth = child.delegation_depth;
+            
+            let parent_account = next_account_info(parent_iter)?;
+            if *parent_account.key != parent_key {
+                return Err(RobyError::InvalidCredential.into());
+            }
+            
+            let parent_data = Self::load_valid_credential(program_id, robot_key, parent_account, now)?;
+            if !parent_data.scope.can_delegate
+                || parent_data.delegation_depth.checked_add(1) != Some(child_depth)
+            {
+                return Err(RobyError::InvalidCredential.into());
+            }
+            
+            chain.push(parent_data);
+        }
+        
+        Ok(chain)
+    }
+    
+    fn load_valid_credential(
+        program_id: &Pubkey,
+        robot_key: &Pubkey,
+        credential_account: &AccountInfo,
+        now: UnixTimestamp,
+    ) -> Result<Credential, ProgramError> {
+        if credential_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let credential_data = Credential::unpack(&credential_account.data.borrow())?;
+        if credential_data.robot != *robot_key || !credential_data.is_valid(now) {
+            return Err(RobyError::InvalidCredential.into());
+        }
+        
+        Ok(credential_data)
+    }
+    
+    /// Checks that the credential at the root of a chain is proven against
+    /// the robot's roots and absent from its revocation tree.
+    fn verify_root_credential(
+        robot_data: &Robot,
+        credential_tree: Option<&CredentialTree>,
+        root_credential: &Credential,
+        proof: &CredentialProof,
+        now: UnixTimestamp,
+    ) -> ProgramResult {
+        let leaf = root_credential.merkle_leaf();
+        if !Self::verify_credential_proof(robot_data, credential_tree, leaf, proof, now) {
+            return Err(RobyError::InvalidMerkleProof.into());
+        }
+        
+        if !proof
+            .revocation_proof
+            .verify_non_membership(&robot_data.revocation_root, &leaf)
+        {
+            return Err(RobyError::CredentialRevoked.into());
+        }
+        
+        Ok(())
+    }
+    
+    fn process_set_operator_policy(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        policy: OperatorPolicy,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.operator_policy = policy;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("Operator policy set to {:?}", policy);
+        Ok(())
+    }
+    
+    fn process_set_command_permission(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        command_type: CommandType,
+        min_level: PermissionLevel,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let robot_account = next_account_info(account_info_iter)?;
+        let authority_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        // Observers never execute commands, whatever the matrix says.
+        if min_level < PermissionLevel::Operator {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        Self::validate_authority(
+            program_id,
+            &robot_data.authority,
+            authority_account,
+            account_info_iter.as_slice(),
+        )?;
+        
+        robot_data.command_permissions[command_type.index()] = min_level;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!("{:?} now requires {:?}", command_type, min_level);
+        Ok(())
+    }
+    
+    /// Authorizes an administrative action. Without `admin_proof` the
+    /// signer must be `expected` (see `validate_authority`) and acts with
+    /// `Owner` rights. With it, the signer must hold a valid, Merkle-proven
+    /// credential of at least `Administrator` level, read from the next
+    /// account (followed by its parents when delegated, then optionally the
+    /// robot's credential tree), and acts with that credential's level.
+    #[allow(clippy::too_many_arguments)]
+    fn validate_admin<'a, 'b>(
+        program_id: &Pubkey,
+        robot_key: &Pubkey,
+        robot_data: &Robot,
+        expected: &Pubkey,
+        signer_account: &AccountInfo<'b>,
+        account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+        admin_proof: Option<&CredentialProof>,
+    ) -> Result<PermissionLevel, ProgramError> {
+        match admin_proof {
+            None => {
+                Self::validate_authority(
+                    program_id,
+                    expected,
+                    signer_account,
+                    account_info_iter.as_slice(),
+                )?;
+                Ok(PermissionLevel::Owner)
+            }
+            Some(proof) => {
+                if !signer_account.is_signer {
+                    return Err(RobyError::NotAuthorized.into());
+                }
+                
+                let now = Clock::get()?.unix_timestamp;
+                let credential_account = next_account_info(account_info_iter)?;
+                let chain = Self::load_credential_chain(
+                    program_id,
+                    robot_key,
+                    signer_account.key,
+                    credential_account,
+                    account_info_iter,
+                    now,
+                )?;
+                let credential_tree = account_info_iter
+                    .next()
+                    .map(|tree_account| Self::load_credential_tree(program_id, robot_key, tree_account))
+                    .transpose()?;
+                let root_credential = chain.last().ok_or(RobyError::InvalidCredential)?;
+                Self::verify_root_credential(
+                    robot_data,
+                    credential_tree.as_ref(),
+                    root_credential,
+                    proof,
+                    now,
+                )?;
+                
+                let permission_level = chain[0].permission_level;
+                if permission_level < PermissionLevel::Administrator {
+                    return Err(RobyError::InsufficientPermissionLevel.into());
+                }
+                
+                Ok(permission_level)
+            }
+        }
+    }
+    
+    /// Issues a sub-credential from a delegable credential held by the
+    /// signer. The new credential's level must be below its parent's, and
+    /// its window and scope must fit within the parent's; it is proven at
+    /// use through the parent chain rather than the robot's credential tree.
+    /// The delegator can later revoke it directly.
+    #[allow(clippy::too_many_arguments)]
+    fn process_delegate_credential(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        permission_level: PermissionLevel,
+        valid_from: i64,
+        valid_until: i64,
+        credential_hash: [u8; 32],
+        scope: CredentialScope,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let credential_account = next_account_info(account_info_iter)?;
+        let robot_account = next_account_info(account_info_iter)?;
+        let delegator_account = next_account_info(account_info_iter)?;
+        let recipient_account = next_account_info(account_info_iter)?;
+        let parent_credential_account = next_account_info(account_info_iter)?;
+        let rent_sysvar = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !delegator_account.is_signer {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        if !scope.is_valid() {
+            return Err(RobyError::InvalidInstruction.into());
+        }
+        
+        let parent_data = Self::load_valid_credential(
+            program_id,
+            robot_account.key,
+            parent_credential_account,
+            Clock::get()?.unix_timestamp,
+        )?;
+        if parent_data.owner != *delegator_account.key {
+            return Err(RobyError::PermissionDenied.into());
+        }
+        
+        if !scope.is_within(&parent_data.scope) {
+            return Err(RobyError::DelegationNotAllowed.into());
+        }
+        
+        // Delegates always sit strictly below their parent's level.
+        if permission_level >= parent_data.permission_level {
+            return Err(RobyError::InsufficientPermissionLevel.into());
+        }
+        
+        if valid_from < parent_data.valid_from
+            || valid_until > parent_data.valid_until
+            || valid_from > valid_until
+        {
+            return Err(RobyError::DelegationNotAllowed.into());
+        }
+        
+        let (credential_address, bump) = Credential::find_address(
+            program_id,
+            robot_account.key,
+            recipient_account.key,
+            &credential_hash,
+        );
+        if credential_address != *credential_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        
+        if credential_account.owner == program_id {
+            return Err(RobyError::AlreadyInitialized.into());
+        }
+        
+        let rent = Rent::from_account_info(rent_sysvar)?;
+        create_pda_account(
+            delegator_account,
+            credential_account,
+            system_program_account,
+            program_id,
+            &rent,
+            Credential::LEN,
+            &[
+                Credential::SEED_PREFIX,
+                robot_account.key.as_ref(),
+                recipient_account.key.as_ref(),
+                &credential_hash,
+                &[bump],
+            ],
+        )?;
+        
+        let mut credential = Credential::new(
+            *recipient_account.key,
+            *robot_account.key,
+            permission_level,
+            valid_from,
+            valid_until,
+            credential_hash,
+            *delegator_account.key,
+            *delegator_account.key,
+            scope,
+        );
+        credential.parent_credential = Some(*parent_credential_account.key);
+        credential.delegation_depth = parent_data.delegation_depth + 1;
+        
+        credential.serialize(&mut &mut credential_account.data.borrow_mut()[..])?;
+        
+        let mut robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        robot_data.open_credentials = robot_data
+            .open_credentials
+            .checked_add(1)
+            .ok_or(RobyError::ArithmeticOverflow)?;
+        robot_data.serialize(&mut &mut robot_account.data.borrow_mut()[..])?;
+        
+        msg!(
+            "Credential delegated to {} at depth {}",
+            recipient_account.key,
+            credential.delegation_depth
+        );
+        Ok(())
+    }
+    
+    /// Accepts proofs against the robot's current root or a root still in
+    /// its grace period, or against any recent root of its on-chain
+    /// credential tree when one is supplied.
+    fn verify_credential_proof(
+        robot: &Robot,
+        credential_tree: Option<&CredentialTree>,
+        leaf: [u8; 32],
+        proof: &CredentialProof,
+        now: UnixTimestamp,
+    ) -> bool {
+        let positional_root = PositionalMerkleProof {
+            proof: proof.merkle_proof.clone(),
+            leaf,
+            index: proof.leaf_index,
+        }
+        .compute_root();
+        
+        let sorted_pair_root = MerkleProof {
+            proof: proof.merkle_proof.clone(),
+            leaf,
+        }
+        .compute_root();
+        
+        let matches_robot_root = robot.accepted_roots(now).any(|(root, scheme)| match scheme {
+            MerkleTreeScheme::SortedPair => sorted_pair_root == root,
+            MerkleTreeScheme::Positional => positional_root == Some(root),
+        });
+        
+        matches_robot_root
+            || match (credential_tree, positional_root) {
+                (Some(tree_data), Some(root)) => tree_data.tree.is_known_root(&root),
+                _ => false,
+            }
+    }
+    
+    fn load_credential_tree(
+        program_id: &Pubkey,
+        robot_key: &Pubkey,
+        tree_account: &AccountInfo,
+    ) -> Result<CredentialTree, ProgramError> {
+        if tree_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let tree_data = CredentialTree::unpack(&tree_account.data.borrow())?;
+        if tree_data.robot != *robot_key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        Ok(tree_data)
+    }
+    
+    fn load_command_queue(
+        program_id: &Pubkey,
+        robot_key: &Pubkey,
+        queue_account: &AccountInfo,
+    ) -> Result<CommandQueue, ProgramError> {
+        if queue_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let queue_data = CommandQueue::unpack(&queue_account.data.borrow())?;
+        if queue_data.robot != *robot_key {
+            return Err(RobyError::InvalidAccountData.into());
+        }
+        
+        Ok(queue_data)
+    }
+    
+    /// Applies a lifecycle transition and emits `StatusChanged` when the
+    /// status actually changes.
+    fn set_status(robot_key: &Pubkey, robot: &mut Robot, next: RobotStatus) -> ProgramResult {
+        let old_status = robot.transition(next)?;
+        
+        if old_status != next {
+            msg!("Status changed: {:?} -> {:?}", old_status, next);
+            StatusChanged {
+                robot: *robot_key,
+                old_status,
+                new_status: next,
+            }
+            .emit();
+        }
+        
+        Ok(())
+    }
+    
+    /// Checks that `authority_account` is `expected` and has approved the
+    /// instruction: either it signed directly, or it is a
+    /// `MultisigAuthority` and at least `threshold` of its signers signed
+    /// among `signer_accounts`.
+    ///
+    /// Instructions that create accounts take a separate payer, so a
+    /// multisig authority never has to fund them itself.
+    fn validate_authority(
+        program_id: &Pubkey,
+        expected: &Pubkey,
+        authority_account: &AccountInfo,
+        signer_accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        if *authority_account.key != *expected {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        if authority_account.is_signer {
+            return Ok(());
+        }
+        
+        if authority_account.owner != program_id {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        let multisig_data = MultisigAuthority::unpack(&authority_account.data.borrow())
+            .map_err(|_| RobyError::NotAuthorized)?;
+        
+        let mut matched = [false; MultisigAuthority::MAX_SIGNERS];
+        let mut approvals = 0usize;
+        for signer_account in signer_accounts.iter().filter(|account| account.is_signer) {
+            if let Some(position) = multisig_data
+                .signers
+                .iter()
+                .position(|signer| signer == signer_account.key)
+            {
+                if !matched[position] {
+                    matched[position] = true;
+                    approvals += 1;
+                }
+            }
+        }
+        
+        if approvals < multisig_data.threshold as usize {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        Ok(())
+    }
+    
+    /// Checks that `approver_account` may propose or approve a resume and
+    /// has signed for it, directly or as a `MultisigAuthority`.
+    fn validate_resume_approver(
+        program_id: &Pubkey,
+        robot_data: &Robot,
+        approver_account: &AccountInfo,
+        signer_accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        if !robot_data.is_resume_approver(approver_account.key) {
+            return Err(RobyError::NotAuthorized.into());
+        }
+        
+        Self::validate_authority(program_id, approver_account.key, approver_account, signer_accounts)
+    }
+    
+    /// Rejects direct calls to sensitive instructions once the robot
+    /// (always their first account) has proposals configured.
+    fn check_proposal_gate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let robot_account = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if robot_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        
+        let robot_data = Robot::unpack(&robot_account.data.borrow())?;
+        if robot_data.proposal_threshold > 0 {
+            return Err(RobyError::ProposalRequired.into());
+        }
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::MerkleTreeScheme;
+    use solana_program::{entrypoint::SUCCESS, program_stubs, sysvar};
+    
+    const NOW: UnixTimestamp = 1_700_000_000;
+    
+    /// Serves `Clock::get` with a fixed `NOW`.
+    struct TestClock;
+    
+    impl program_stubs::SyscallStubs for TestClock {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock { unix_timestamp: NOW, ..Clock::default() };
+            unsafe { std::ptr::write_unaligned(var_addr as *mut Clock, clock) };
+            SUCCESS
+        }
+    }
+    
+    fn use_test_clock() {
+        program_stubs::set_syscall_stubs(Box::new(TestClock));
+    }
+    
+    /// Backing storage for an `AccountInfo`, laid out like the runtime's
+    /// serialized input (length before the key, length before the data) so
+    /// `close_account` can reassign and shrink it.
+    #[repr(C)]
+    struct TestAccount {
+        original_data_len: u32,
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        is_signer: bool,
+        data: Vec<u8>,
+    }
+    
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: &[u8]) -> Self {
+            let mut buffer = (data.len() as u64).to_le_bytes().to_vec();
+            buffer.extend_from_slice(data);
+            Self {
+                original_data_len: data.len() as u32,
+                key,
+                owner,
+                lamports: 1_000,
+                is_signer: false,
+                data: buffer,
+            }
+        }
+        
+        fn program<T: BorshSerialize>(program_id: &Pubkey, key: Pubkey, value: &T, len: usize) -> Self {
+            let mut data = vec![0u8; len];
+            value.serialize(&mut &mut data[..]).unwrap();
+            Self::new(key, *program_id, &data)
+        }
+        
+        fn wallet(key: Pubkey) -> Self {
+            Self::new(key, system_program::id(), &[])
+        }
+        
+        fn signer(key: Pubkey) -> Self {
+            Self { is_signer: true, ..Self::wallet(key) }
+        }
+        
+        fn clock() -> Self {
+            let mut account = Self::new(sysvar::clock::id(), sysvar::id(), &vec![0u8; Clock::size_of()]);
+            Clock { unix_timestamp: NOW, ..Clock::default() }
+                .to_account_info(&mut account.info())
+                .unwrap();
+            account
+        }
+        
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                self.is_signer,
+                true,
+                &mut self.lamports,
+                &mut self.data[8..],
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+    
+    fn robot(owner: Pubkey, authority: Pubkey) -> Robot {
+        Robot::new(
+            owner,
+            authority,
+            Pubkey::new_unique(),
+            [1u8; 32],
+            [0u8; 32],
+            MerkleTreeScheme::SortedPair,
+            String::new(),
+        )
+    }
+    
+    fn robot_data(robot: &Robot) -> Vec<u8> {
+        let mut data = vec![0u8; Robot::LEN];
+        robot.serialize(&mut &mut data[..]).unwrap();
+        data
+    }
+    
+    fn credential(holder: Pubkey, robot_key: Pubkey, permission_level: PermissionLevel) -> Credential {
+        Credential::new(
+            holder,
+            robot_key,
+            permission_level,
+            NOW - 100,
+            NOW + 100,
+            [7u8; 32],
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            CredentialScope::default(),
+        )
+    }
+    
+    /// Runs `ExecuteCommand` for `executor` against the robot's current
+    /// sequence, with fresh log and queue accounts.
+    fn execute(
+        program_id: &Pubkey,
+        robot_account: &mut TestAccount,
+        executor: Pubkey,
+        credential: &Credential,
+        command_type: CommandType,
+    ) -> ProgramResult {
+        let robot_data = Robot::unpack(&robot_account.data[8..]).unwrap();
+        execute_at(
+            program_id,
+            robot_account,
+            executor,
+            credential,
+            command_type,
+            robot_data.command_sequence,
+        )
+    }
+    
+    fn execute_at(
+        program_id: &Pubkey,
+        robot_account: &mut TestAccount,
+        executor: Pubkey,
+        credential: &Credential,
+        command_type: CommandType,
+        expected_sequence: u64,
+    ) -> ProgramResult {
+        let (queue_key, _) = CommandQueue::find_address(program_id, &robot_account.key);
+        let mut executor_account = TestAccount::signer(executor);
+        let mut credential_account =
+            TestAccount::program(program_id, Pubkey::new_unique(), credential, Credential::LEN);
+        let mut log_account =
+            TestAccount::new(Pubkey::new_unique(), *program_id, &[0u8; CommandLog::LEN]);
+        let mut clock_account = TestAccount::clock();
+        let mut queue_account = TestAccount::program(
+            program_id,
+            queue_key,
+            &CommandQueue::new(robot_account.key),
+            CommandQueue::LEN,
+        );
+        
+        let accounts = [
+            robot_account.info(),
+            executor_account.info(),
+            credential_account.info(),
+            log_account.info(),
+            clock_account.info(),
+            queue_account.info(),
+        ];
+        let instruction = RobyInstruction::ExecuteCommand {
+            command_type,
+            parameters: Vec::new(),
+            proof: CredentialProof::default(),
+            expected_sequence,
+        };
+        Processor::process(program_id, &accounts, &instruction.pack())
+    }
+    
+    #[test]
+    fn test_stopped_robot_locks_resume_policy() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        
+        let mut robot = robot(owner, authority);
+        robot.guardians.push(Pubkey::new_unique());
+        robot.resume_threshold = 2;
+        robot.emergency_stop = true;
+        
+        let mut robot_lamports = 0;
+        let mut robot_bytes = robot_data(&robot);
+        let robot_account = AccountInfo::new(
+            &robot_key, false, true, &mut robot_lamports, &mut robot_bytes, &program_id, false, 0,
+        );
+        let mut authority_lamports = 0;
+        let mut authority_bytes = Vec::new();
+        let system_id = system_program::id();
+        let authority_account = AccountInfo::new(
+            &authority, true, false, &mut authority_lamports, &mut authority_bytes, &system_id, false, 0,
+        );
+        let accounts = [robot_account, authority_account];
+        
+        let lower = RobyInstruction::SetResumePolicy {
+            resume_threshold: 0,
+            proposal_ttl: robot.resume_proposal_ttl,
+        };
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &lower.pack()),
+            Err(RobyError::ResumePolicyLocked.into())
+        );
+        
+        let add = RobyInstruction::AddGuardian { guardian: Pubkey::new_unique() };
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &add.pack()),
+            Err(RobyError::ResumePolicyLocked.into())
+        );
+        
+        let remove = RobyInstruction::RemoveGuardian { guardian: robot.guardians[0] };
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &remove.pack()),
+            Err(RobyError::ResumePolicyLocked.into())
+        );
+        
+        let raise = RobyInstruction::SetResumePolicy {
+            resume_threshold: 3,
+            proposal_ttl: robot.resume_proposal_ttl,
+        };
+        assert_eq!(Processor::process(&program_id, &accounts, &raise.pack()), Ok(()));
+        
+        let stored = Robot::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(stored.resume_threshold, 3);
+        assert_eq!(stored.guardians, robot.guardians);
+    }
+    
+    #[test]
+    fn test_validate_authority_direct_signer() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let system_id = system_program::id();
+        let (mut signed_lamports, mut unsigned_lamports) = (0, 0);
+        let (mut signed_data, mut unsigned_data) = (Vec::new(), Vec::new());
+        let signed = AccountInfo::new(
+            &authority, true, false, &mut signed_lamports, &mut signed_data, &system_id, false, 0,
+        );
+        let unsigned = AccountInfo::new(
+            &authority, false, false, &mut unsigned_lamports, &mut unsigned_data, &system_id, false, 0,
+        );
+        
+        assert!(Processor::validate_authority(&program_id, &authority, &signed, &[]).is_ok());
+        assert!(Processor::validate_authority(&program_id, &authority, &unsigned, &[]).is_err());
+        assert!(
+            Processor::validate_authority(&program_id, &Pubkey::new_unique(), &signed, &[]).is_err()
+        );
+    }
+    
+    #[test]
+    fn test_validate_authority_multisig_threshold() {
+        let program_id = Pubkey::new_unique();
+        let system_id = system_program::id();
+        let multisig_key = Pubkey::new_unique();
+        let members = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let outsider = Pubkey::new_unique();
+        
+        let multisig = MultisigAuthority::new(members.to_vec(), 2);
+        let mut multisig_lamports = 0;
+        let mut multisig_data = vec![0u8; MultisigAuthority::LEN];
+        multisig.serialize(&mut &mut multisig_data[..]).unwrap();
+        let multisig_account = AccountInfo::new(
+            &multisig_key, false, false, &mut multisig_lamports, &mut multisig_data, &program_id,
+            false, 0,
+        );
+        
+        let mut lamports = [0u64; 4];
+        let mut data = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        let keys = [members[0], members[0], members[1], outsider];
+        let signers: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, true, false, lamports, data, &system_id, false, 0)
+            })
+            .collect();
+        
+        let validate = |signer_accounts: &[AccountInfo]| {
+            Processor::validate_authority(&program_id, &multisig_key, &multisig_account, signer_accounts)
+        };
+        
+        // A repeated member and an outsider do not make up the threshold.
+        assert!(validate(&[signers[0].clone(), signers[1].clone(), signers[3].clone()]).is_err());
+        assert!(validate(&[signers[0].clone(), signers[2].clone()]).is_ok());
+        
+        let mut unsigned = signers[2].clone();
+        unsigned.is_signer = false;
+        assert!(validate(&[signers[0].clone(), unsigned]).is_err());
+    }
+    
+    #[test]
+    fn test_authority_approvals_cannot_transfer_ownership() {
+        let owner = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let mut robot = robot(owner, authority);
+        robot.proposal_approvers = vec![authority];
+        robot.proposal_threshold = 1;
+        
+        let transfer = RobyInstruction::TransferOwnership { new_owner: authority };
+        let mut proposal = Proposal::new(Pubkey::new_unique(), authority, 0, transfer.pack(), 10);
+        proposal.approve(&robot, authority, 10);
+        assert!(proposal.is_executable(&robot, 10));
+        assert_eq!(
+            Processor::proposal_signer(&robot, &proposal, &transfer),
+            Err(RobyError::NotAuthorized.into())
+        );
+        
+        proposal.approve(&robot, owner, 20);
+        assert_eq!(Processor::proposal_signer(&robot, &proposal, &transfer), Ok(owner));
+        
+        // Authority-gated instructions only ever sign for the authority.
+        let root = RobyInstruction::UpdateMerkleRoot {
+            new_merkle_root: [2u8; 32],
+            tree_scheme: MerkleTreeScheme::SortedPair,
+        };
+        assert_eq!(Processor::proposal_signer(&robot, &proposal, &root), Ok(authority));
+    }
+    
+    #[test]
+    fn test_multisig_owner_and_authority_can_resume() {
+        use_test_clock();
+        let program_id = Pubkey::new_unique();
+        let (owner_signer, authority_signer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let owner_multisig = MultisigAuthority::new(vec![owner_signer], 1);
+        let authority_multisig = MultisigAuthority::new(vec![authority_signer], 1);
+        let (owner, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        let mut robot = robot(owner, authority);
+        robot.emergency_stop = true;
+        robot.emergency_stopped_at = NOW - 100;
+        robot.resume_threshold = 2;
+        robot.status = RobotStatus::Error;
+        
+        let robot_key = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (proposal_key, _) = ResumeProposal::find_address(&program_id, &robot_key);
+        let mut expired = ResumeProposal::new(robot_key, authority, NOW - 90, 10);
+        expired.payer = payer;
+        
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut proposal_account =
+            TestAccount::program(&program_id, proposal_key, &expired, ResumeProposal::LEN);
+        let mut owner_account =
+            TestAccount::program(&program_id, owner, &owner_multisig, MultisigAuthority::LEN);
+        let mut authority_account =
+            TestAccount::program(&program_id, authority, &authority_multisig, MultisigAuthority::LEN);
+        let mut payer_account = TestAccount::signer(Pubkey::new_unique());
+        let mut refund_account = TestAccount::wallet(payer);
+        let mut rent_account = TestAccount::wallet(sysvar::rent::id());
+        let mut system_account = TestAccount::wallet(system_program::id());
+        let mut owner_signer_account = TestAccount::signer(owner_signer);
+        let mut authority_signer_account = TestAccount::signer(authority_signer);
+        
+        let propose = [
+            proposal_account.info(),
+            robot_account.info(),
+            owner_account.info(),
+            payer_account.info(),
+            rent_account.info(),
+            system_account.info(),
+            owner_signer_account.info(),
+        ];
+        Processor::process(&program_id, &propose, &RobyInstruction::ProposeResume.pack()).unwrap();
+        
+        let approve = [
+            propose[0].clone(),
+            propose[1].clone(),
+            authority_account.info(),
+            authority_signer_account.info(),
+        ];
+        Processor::process(&program_id, &approve, &RobyInstruction::ApproveResume.pack()).unwrap();
+        
+        let resume = [
+            propose[1].clone(),
+            approve[2].clone(),
+            propose[0].clone(),
+            refund_account.info(),
+            approve[3].clone(),
+        ];
+        Processor::process(&program_id, &resume, &RobyInstruction::Resume.pack()).unwrap();
+        
+        let resumed = Robot::unpack(&resume[0].data.borrow()).unwrap();
+        assert!(!resumed.emergency_stop);
+        assert_eq!(resumed.status, RobotStatus::Idle);
+        
+        // The replaced proposal still refunds the payer of the original.
+        assert_eq!(resume[2].lamports(), 0);
+        assert_eq!(resume[3].lamports(), 2_000);
+    }
+    
+    #[test]
+    fn test_close_robot_closes_keyed_dependents() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let resume_payer = Pubkey::new_unique();
+        let (queue_key, _) = CommandQueue::find_address(&program_id, &robot_key);
+        let (tree_key, _) = CredentialTree::find_address(&program_id, &robot_key);
+        let (proposal_key, _) = ResumeProposal::find_address(&program_id, &robot_key);
+        let mut proposal = ResumeProposal::new(robot_key, owner, NOW, 60);
+        proposal.payer = resume_payer;
+        
+        let robot = robot(owner, Pubkey::new_unique());
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut owner_account = TestAccount::signer(owner);
+        let mut receiver_account = TestAccount::wallet(Pubkey::new_unique());
+        let mut queue_account =
+            TestAccount::program(&program_id, queue_key, &CommandQueue::new(robot_key), CommandQueue::LEN);
+        let mut tree_account = TestAccount::wallet(tree_key);
+        let mut proposal_account =
+            TestAccount::program(&program_id, proposal_key, &proposal, ResumeProposal::LEN);
+        let mut resume_payer_account = TestAccount::wallet(resume_payer);
+        
+        let accounts = [
+            robot_account.info(),
+            owner_account.info(),
+            receiver_account.info(),
+            queue_account.info(),
+            tree_account.info(),
+            proposal_account.info(),
+            resume_payer_account.info(),
+        ];
+        Processor::process(&program_id, &accounts, &RobyInstruction::CloseRobot.pack()).unwrap();
+        
+        for closed in [&accounts[0], &accounts[3], &accounts[5]] {
+            assert_eq!(closed.lamports(), 0);
+            assert_eq!(*closed.owner, system_program::id());
+            assert_eq!(closed.data_len(), 0);
+        }
+        assert_eq!(accounts[2].lamports(), 3_000);
+        assert_eq!(accounts[4].lamports(), 1_000);
+        assert_eq!(accounts[6].lamports(), 2_000);
+    }
+    
+    #[test]
+    fn test_expired_credential_refunds_its_payer() {
+        let program_id = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let issuer = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        
+        let mut robot = robot(Pubkey::new_unique(), issuer);
+        robot.open_credentials = 1;
+        let credential = Credential::new(
+            Pubkey::new_unique(),
+            robot_key,
+            PermissionLevel::Operator,
+            NOW - 100,
+            NOW - 1,
+            [3u8; 32],
+            issuer,
+            payer,
+            CredentialScope::default(),
+        );
+        
+        let mut credential_account =
+            TestAccount::program(&program_id, Pubkey::new_unique(), &credential, Credential::LEN);
+        let mut issuer_account = TestAccount::wallet(issuer);
+        let mut payer_account = TestAccount::wallet(payer);
+        let mut closer_account = TestAccount::wallet(Pubkey::new_unique());
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut clock_account = TestAccount::clock();
+        let close = RobyInstruction::CloseCredential.pack();
+        
+        let to_issuer = [
+            credential_account.info(),
+            issuer_account.info(),
+            closer_account.info(),
+            robot_account.info(),
+            clock_account.info(),
+        ];
+        assert_eq!(
+            Processor::process(&program_id, &to_issuer, &close),
+            Err(RobyError::NotAuthorized.into())
+        );
+        
+        let to_payer = [
+            to_issuer[0].clone(),
+            payer_account.info(),
+            to_issuer[2].clone(),
+            to_issuer[3].clone(),
+            to_issuer[4].clone(),
+        ];
+        Processor::process(&program_id, &to_payer, &close).unwrap();
+        
+        assert_eq!(to_payer[0].lamports(), 0);
+        assert_eq!(to_payer[1].lamports(), 2_000);
+        assert_eq!(Robot::unpack(&to_payer[3].data.borrow()).unwrap().open_credentials, 0);
+    }
+    
+    #[test]
+    fn test_command_logs_outlive_their_robot() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let executor = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        
+        let mut log = CommandLog::new(robot_key, executor, CommandType::Move, NOW, 0, Vec::new());
+        log.status = CommandStatus::Started;
+        
+        let robot = robot(Pubkey::new_unique(), authority);
+        let mut log_account =
+            TestAccount::program(&program_id, Pubkey::new_unique(), &log, CommandLog::LEN);
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut closed_robot_account = TestAccount::wallet(robot_key);
+        let mut authority_account = TestAccount::signer(authority);
+        let mut receiver_account = TestAccount::wallet(Pubkey::new_unique());
+        let mut executor_account = TestAccount::wallet(executor);
+        let close = RobyInstruction::CloseCommandLog.pack();
+        
+        // While the robot exists the queue still needs the unfinished log.
+        let live = [
+            log_account.info(),
+            robot_account.info(),
+            authority_account.info(),
+            receiver_account.info(),
+        ];
+        assert_eq!(
+            Processor::process(&program_id, &live, &close),
+            Err(RobyError::InvalidCommandStatus.into())
+        );
+        
+        let orphaned = [
+            live[0].clone(),
+            closed_robot_account.info(),
+            live[3].clone(),
+            live[3].clone(),
+        ];
+        assert_eq!(
+            Processor::process(&program_id, &orphaned, &close),
+            Err(RobyError::NotAuthorized.into())
+        );
+        
+        let to_executor = [
+            live[0].clone(),
+            orphaned[1].clone(),
+            live[3].clone(),
+            executor_account.info(),
+        ];
+        Processor::process(&program_id, &to_executor, &close).unwrap();
+        assert_eq!(to_executor[0].lamports(), 0);
+        assert_eq!(to_executor[3].lamports(), 2_000);
+    }
+    
+    #[test]
+    fn test_co_signed_rotation_needs_the_old_device_key() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let robot = robot(Pubkey::new_unique(), authority);
+        let new_device_key = Pubkey::new_unique();
+        
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut authority_account = TestAccount::signer(authority);
+        let mut unsigned_device_account = TestAccount::wallet(robot.device_key);
+        let mut other_account = TestAccount::signer(Pubkey::new_unique());
+        let mut device_account = TestAccount::signer(robot.device_key);
+        let rotate = RobyInstruction::RotateDeviceKey { new_device_key, co_signed: true }.pack();
+        
+        let robot_info = robot_account.info();
+        let authority_info = authority_account.info();
+        for old_device_info in [unsigned_device_account.info(), other_account.info()] {
+            let accounts = [robot_info.clone(), authority_info.clone(), old_device_info];
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &rotate),
+                Err(RobyError::NotAuthorized.into())
+            );
+        }
+        
+        let accounts = [robot_info.clone(), authority_info.clone(), device_account.info()];
+        Processor::process(&program_id, &accounts, &rotate).unwrap();
+        assert_eq!(Robot::unpack(&robot_info.data.borrow()).unwrap().device_key, new_device_key);
+        
+        // Without `co_signed` the authority alone is enough.
+        let rotate_back = RobyInstruction::RotateDeviceKey {
+            new_device_key: robot.device_key,
+            co_signed: false,
+        };
+        Processor::process(&program_id, &accounts[..2], &rotate_back.pack()).unwrap();
+        assert_eq!(Robot::unpack(&robot_info.data.borrow()).unwrap().device_key, robot.device_key);
+    }
+    
+    #[test]
+    fn test_operator_list_only_admits_listed_operators() {
+        let program_id = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        let outsider = Pubkey::new_unique();
+        
+        let mut robot = robot(Pubkey::new_unique(), Pubkey::new_unique());
+        robot.operator_policy = OperatorPolicy::OperatorListOnly;
+        robot.active_operators.push(operator);
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        
+        // The credential slot is not read, so an unproven credential is fine.
+        let unproven = credential(operator, robot_key, PermissionLevel::Operator);
+        assert_eq!(
+            execute(&program_id, &mut robot_account, operator, &unproven, CommandType::Move),
+            Ok(())
+        );
+        assert_eq!(
+            execute(&program_id, &mut robot_account, outsider, &unproven, CommandType::Move),
+            Err(RobyError::PermissionDenied.into())
+        );
+    }
+    
+    #[test]
+    fn test_removed_operator_is_locked_out_immediately() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        
+        let operator_credential = credential(operator, robot_key, PermissionLevel::Operator);
+        let mut robot = robot(Pubkey::new_unique(), authority);
+        robot.merkle_root = operator_credential.merkle_leaf();
+        robot.operator_policy = OperatorPolicy::CredentialAndOperatorList;
+        robot.active_operators.push(operator);
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        
+        assert_eq!(
+            execute(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move),
+            Ok(())
+        );
+        
+        let mut authority_account = TestAccount::signer(authority);
+        let remove = RobyInstruction::RemoveOperator { operator, admin_proof: None };
+        Processor::process(
+            &program_id,
+            &[robot_account.info(), authority_account.info()],
+            &remove.pack(),
+        )
+        .unwrap();
+        
+        // The credential is still valid, but the listing is gone.
+        assert_eq!(
+            execute(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move),
+            Err(RobyError::PermissionDenied.into())
+        );
+    }
+    
+    #[test]
+    fn test_credential_only_ignores_operator_list() {
+        let program_id = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let listed = Pubkey::new_unique();
+        
+        let holder_credential = credential(holder, robot_key, PermissionLevel::Operator);
+        let mut robot = robot(Pubkey::new_unique(), Pubkey::new_unique());
+        robot.merkle_root = holder_credential.merkle_leaf();
+        robot.active_operators.push(listed);
+        assert_eq!(robot.operator_policy, OperatorPolicy::CredentialOnly);
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        
+        assert_eq!(
+            execute(&program_id, &mut robot_account, holder, &holder_credential, CommandType::Move),
+            Ok(())
+        );
+        
+        // Being listed does not stand in for a credential of one's own.
+        assert_eq!(
+            execute(&program_id, &mut robot_account, listed, &holder_credential, CommandType::Move),
+            Err(RobyError::PermissionDenied.into())
+        );
+    }
+    
+    #[test]
+    fn test_command_permission_matrix() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        
+        let operator_credential = credential(operator, robot_key, PermissionLevel::Operator);
+        let mut robot = robot(Pubkey::new_unique(), authority);
+        robot.merkle_root = operator_credential.merkle_leaf();
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut authority_account = TestAccount::signer(authority);
+        
+        let set_permission = |robot_account: &mut TestAccount,
+                              authority_account: &mut TestAccount,
+                              min_level: PermissionLevel| {
+            let instruction = RobyInstruction::SetCommandPermission {
+                command_type: CommandType::Grab,
+                min_level,
+            };
+            Processor::process(
+                &program_id,
+                &[robot_account.info(), authority_account.info()],
+                &instruction.pack(),
+            )
+        };
+        
+        for below_operator in [PermissionLevel::None, PermissionLevel::Observer] {
+            assert_eq!(
+                set_permission(&mut robot_account, &mut authority_account, below_operator),
+                Err(RobyError::InvalidInstruction.into())
+            );
+        }
+        
+        set_permission(&mut robot_account, &mut authority_account, PermissionLevel::Administrator)
+            .unwrap();
+        assert_eq!(
+            execute(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Grab),
+            Err(RobyError::InsufficientPermissionLevel.into())
+        );
+        assert_eq!(
+            execute(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_admin_credentials_grant_admin_rights_below_their_level() {
+        use_test_clock();
+        let program_id = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let (admin, operator) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let admin_credential = credential(admin, robot_key, PermissionLevel::Administrator);
+        let operator_credential = credential(operator, robot_key, PermissionLevel::Operator);
+        let mut robot = robot(Pubkey::new_unique(), Pubkey::new_unique());
+        robot.merkle_root = admin_credential.merkle_leaf();
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        let mut admin_account = TestAccount::signer(admin);
+        let mut operator_account = TestAccount::signer(operator);
+        let mut admin_credential_account =
+            TestAccount::program(&program_id, Pubkey::new_unique(), &admin_credential, Credential::LEN);
+        let mut operator_credential_account =
+            TestAccount::program(&program_id, Pubkey::new_unique(), &operator_credential, Credential::LEN);
+        let mut peer_credential_account = TestAccount::program(
+            &program_id,
+            Pubkey::new_unique(),
+            &credential(Pubkey::new_unique(), robot_key, PermissionLevel::Administrator),
+            Credential::LEN,
+        );
+        let mut new_credential_account = TestAccount::wallet(Pubkey::new_unique());
+        let mut recipient_account = TestAccount::wallet(Pubkey::new_unique());
+        let mut rent_account = TestAccount::wallet(sysvar::rent::id());
+        let mut system_account = TestAccount::wallet(system_program::id());
+
+        let robot_info = robot_account.info();
+        let admin_info = admin_account.info();
+        let admin_credential_info = admin_credential_account.info();
+        let operator_credential_info = operator_credential_account.info();
+        let admin_proof = Some(CredentialProof::default());
+
+        let add_operator = RobyInstruction::AddOperator {
+            operator: Pubkey::new_unique(),
+            admin_proof: admin_proof.clone(),
+        };
+        Processor::process(
+            &program_id,
+            &[robot_info.clone(), admin_info.clone(), admin_credential_info.clone()],
+            &add_operator.pack(),
+        )
+        .unwrap();
+
+        // An operator credential is not an admin credential, even when proven.
+        let mut operator_robot = robot.clone();
+        operator_robot.merkle_root = operator_credential.merkle_leaf();
+        let mut operator_robot_account =
+            TestAccount::program(&program_id, robot_key, &operator_robot, Robot::LEN);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    operator_robot_account.info(),
+                    operator_account.info(),
+                    operator_credential_info.clone(),
+                ],
+                &add_operator.pack(),
+            ),
+            Err(RobyError::InsufficientPermissionLevel.into())
+        );
+
+        let issue_peer = RobyInstruction::IssueCredential {
+            permission_level: PermissionLevel::Administrator,
+            valid_from: NOW,
+            valid_until: NOW + 100,
+            credential_hash: [9u8; 32],
+            scope: CredentialScope::default(),
+            append_to_tree: false,
+            admin_proof: admin_proof.clone(),
+        };
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    new_credential_account.info(),
+                    robot_info.clone(),
+                    admin_info.clone(),
+                    admin_info.clone(),
+                    recipient_account.info(),
+                    rent_account.info(),
+                    system_account.info(),
+                    admin_credential_info.clone(),
+                ],
+                &issue_peer.pack(),
+            ),
+            Err(RobyError::InsufficientPermissionLevel.into())
+        );
+
+        let revoke = RobyInstruction::RevokeCredential { admin_proof };
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    peer_credential_account.info(),
+                    admin_info.clone(),
+                    robot_info.clone(),
+                    admin_credential_info.clone(),
+                ],
+                &revoke.pack(),
+            ),
+            Err(RobyError::InsufficientPermissionLevel.into())
+        );
+        Processor::process(
+            &program_id,
+            &[
+                operator_credential_info.clone(),
+                admin_info.clone(),
+                robot_info.clone(),
+                admin_credential_info.clone(),
+            ],
+            &revoke.pack(),
+        )
+        .unwrap();
+        assert!(Credential::unpack(&operator_credential_info.data.borrow()).unwrap().revoked);
+    }
+    
+    #[test]
+    fn test_commands_must_match_the_robot_sequence() {
+        let program_id = Pubkey::new_unique();
+        let robot_key = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        
+        let operator_credential = credential(operator, robot_key, PermissionLevel::Operator);
+        let mut robot = robot(Pubkey::new_unique(), Pubkey::new_unique());
+        robot.merkle_root = operator_credential.merkle_leaf();
+        let mut robot_account = TestAccount::program(&program_id, robot_key, &robot, Robot::LEN);
+        
+        assert_eq!(
+            execute_at(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move, 1),
+            Err(RobyError::InvalidCommandSequence.into())
+        );
+        assert_eq!(
+            execute_at(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move, 0),
+            Ok(())
+        );
+        assert_eq!(Robot::unpack(&robot_account.data[8..]).unwrap().command_sequence, 1);
+        
+        // Replaying the command that was just accepted is rejected.
+        assert_eq!(
+            execute_at(&program_id, &mut robot_account, operator, &operator_credential, CommandType::Move, 0),
+            Err(RobyError::InvalidCommandSequence.into())
+        );
+    }
+    
+    #[test]
+    fn test_only_listed_stoppers_can_emergency_stop() {
+        use_test_clock();
+        let program_id = Pubkey::new_unique();
+        let guardian = Pubkey::new_unique();
+        let mut robot = robot(Pubkey::new_unique(), Pubkey::new_unique());
+        robot.guardians.push(guardian);
+        let mut robot_account =
+            TestAccount::program(&program_id, Pubkey::new_unique(), &robot, Robot::LEN);
+        let stop = RobyInstruction::EmergencyStop { reason_code: 7 }.pack();
+        
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[robot_account.info(), TestAccount::signer(Pubkey::new_unique()).info()],
+                &stop,
+            ),
+            Err(RobyError::NotAuthorized.into())
+        );
+        
+        let mut guardian_account = TestAccount::signer(guardian);
+        Processor::process(&program_id, &[robot_account.info(), guardian_account.info()], &stop)
+            .unwrap();
+        let robot = Robot::unpack(&robot_account.data[8..]).unwrap();
+        assert!(robot.emergency_stop);
+        assert_eq!(robot.emergency_stop_reason, 7);
+        assert_eq!(robot.emergency_stopped_by, guardian);
+        assert_eq!(robot.emergency_stopped_at, NOW);
+    }
+    
+    #[test]
+    fn test_transfers_wait_for_the_new_key() {
+        let program_id = Pubkey::new_unique();
+        let (owner, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (new_owner, new_authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut robot_account = TestAccount::program(
+            &program_id,
+            Pubkey::new_unique(),
+            &robot(owner, authority),
+            Robot::LEN,
+        );
+        let mut process_as = |signer: Pubkey, instruction: RobyInstruction| {
+            let mut signer_account = TestAccount::signer(signer);
+            let result = Processor::process(
+                &program_id,
+                &[robot_account.info(), signer_account.info()],
+                &instruction.pack(),
+            );
+            (result, Robot::unpack(&robot_account.data[8..]).unwrap())
+        };
+        
+        let (result, robot) = process_as(owner, RobyInstruction::TransferOwnership { new_owner });
+        assert_eq!(result, Ok(()));
+        assert_eq!((robot.owner, robot.pending_owner), (owner, Some(new_owner)));
+        assert_eq!(
+            process_as(Pubkey::new_unique(), RobyInstruction::AcceptOwnership).0,
+            Err(RobyError::NotAuthorized.into())
+        );
+        let (result, robot) = process_as(new_owner, RobyInstruction::AcceptOwnership);
+        assert_eq!(result, Ok(()));
+        assert_eq!((robot.owner, robot.pending_owner), (new_owner, None));
+        assert_eq!(
+            process_as(new_owner, RobyInstruction::AcceptOwnership).0,
+            Err(RobyError::InvalidRobotState.into())
+        );
+        
+        let transfer_authority = || RobyInstruction::TransferAuthority { new_authority };
+        assert_eq!(process_as(authority, transfer_authority()).0, Ok(()));
+        let (result, robot) = process_as(authority, RobyInstruction::CancelTransfer);
+        assert_eq!(result, Ok(()));
+        assert_eq!((robot.authority, robot.pending_authority), (authority, None));
+        assert_eq!(
+            process_as(new_authority, RobyInstruction::AcceptAuthority).0,
+            Err(RobyError::InvalidRobotState.into())
+        );
+        
+        // The owner's cancel only withdraws a pending ownership transfer.
+        assert_eq!(process_as(authority, transfer_authority()).0, Ok(()));
+        let (result, robot) = process_as(new_owner, RobyInstruction::CancelTransfer);
+        assert_eq!(result, Ok(()));
+        assert_eq!(robot.pending_authority, Some(new_authority));
+        assert_eq!(
+            process_as(Pubkey::new_unique(), RobyInstruction::CancelTransfer).0,
+            Err(RobyError::NotAuthorized.into())
+        );
+        let (result, robot) = process_as(new_authority, RobyInstruction::AcceptAuthority);
+        assert_eq!(result, Ok(()));
+        assert_eq!((robot.authority, robot.pending_authority), (new_authority, None));
+    }
+}
+
 
 
 
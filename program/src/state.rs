@@ -1,11 +1,51 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     pubkey::Pubkey,
+    program_error::ProgramError,
     program_pack::{IsInitialized, Sealed},
-    clock::UnixTimestamp,
+    clock::{Slot, UnixTimestamp},
 };
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+use crate::{error::RobyError, merkle::{self, IncrementalMerkleTree, MerkleTreeScheme}};
+
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AccountType {
+    Robot,
+    Credential,
+    CommandLog,
+}
+
+/// Common header handling for every account owned by the program.
+///
+/// Each account starts with an 8-byte discriminator followed by a one-byte
+/// layout version, so an account of one type can never be read as another.
+pub trait RobyAccount: BorshSerialize + BorshDeserialize {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+    const VERSION: u8;
+    
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() <= DISCRIMINATOR_LEN {
+            return Err(RobyError::InvalidAccountType.into());
+        }
+        
+        let discriminator = &data[..DISCRIMINATOR_LEN];
+        if discriminator == [0u8; DISCRIMINATOR_LEN] {
+            return Err(RobyError::UninitializedAccount.into());
+        }
+        if discriminator != Self::DISCRIMINATOR {
+            return Err(RobyError::InvalidAccountType.into());
+        }
+        if data[DISCRIMINATOR_LEN] != Self::VERSION {
+            return Err(RobyError::AccountVersionMismatch.into());
+        }
+        
+        Self::deserialize(&mut &data[..]).map_err(|_| RobyError::InvalidAccountData.into())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
 pub enum RobotStatus {
     Offline,
     Idle,
@@ -15,7 +55,58 @@ pub enum RobotStatus {
     Maintenance,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+impl RobotStatus {
+    /// The robot lifecycle. Staying in the same status is always allowed.
+    ///
+    /// | from        | to                                          |
+    /// |-------------|---------------------------------------------|
+    /// | Offline     | Idle, Error                                 |
+    /// | Idle        | Offline, Active, Executing, Error, Maintenance |
+    /// | Active      | Offline, Idle, Executing, Error             |
+    /// | Executing   | Offline, Idle, Active, Error                |
+    /// | Error       | Offline, Idle, Maintenance                  |
+    /// | Maintenance | Offline, Idle, Error                        |
+    ///
+    /// `Executing` is only entered when the robot acknowledges a started
+    /// command; `Error` is reachable from everywhere so faults and emergency
+    /// stops can always be recorded.
+    pub fn can_transition_to(&self, next: RobotStatus) -> bool {
+        use RobotStatus::*;
+        
+        *self == next
+            || matches!(
+                (self, next),
+                (_, Error)
+                    | (Offline, Idle)
+                    | (Idle, Offline | Active | Executing | Maintenance)
+                    | (Active, Offline | Idle | Executing)
+                    | (Executing, Offline | Idle | Active)
+                    | (Error, Offline | Idle | Maintenance)
+                    | (Maintenance, Offline | Idle)
+            )
+    }
+    
+    /// Statuses in which the robot accepts and runs commands.
+    pub fn is_operational(&self) -> bool {
+        matches!(self, RobotStatus::Idle | RobotStatus::Active | RobotStatus::Executing)
+    }
+}
+
+/// Who may run `ExecuteCommand` on a robot. The list-based policies make
+/// `RemoveOperator` take effect immediately, even while the removed
+/// operator's credential is still valid.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OperatorPolicy {
+    /// A valid credential is enough; `active_operators` is not consulted.
+    CredentialOnly,
+    /// Only keys in `active_operators`, without a credential.
+    OperatorListOnly,
+    /// A valid credential held by a key in `active_operators`.
+    CredentialAndOperatorList,
+}
+
+/// Ordered from least to most privileged.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PermissionLevel {
     None,
     Observer,
@@ -26,58 +117,438 @@ pub enum PermissionLevel {
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct Robot {
-    pub is_initialized: bool,
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
     pub owner: Pubkey,
     pub authority: Pubkey,
+    /// Keys named by `TransferOwnership`/`TransferAuthority` that still
+    /// have to accept the role.
+    pub pending_owner: Option<Pubkey>,
+    pub pending_authority: Option<Pubkey>,
+    /// Key held by the robot itself, used to acknowledge queued commands.
+    pub device_key: Pubkey,
     pub status: RobotStatus,
     pub robot_id: [u8; 32],
     pub merkle_root: [u8; 32],
+    pub tree_scheme: MerkleTreeScheme,
+    pub revocation_root: [u8; 32],
+    pub recent_roots: Vec<RetiredRoot>,
+    pub root_grace_period: i64,
     pub last_command_timestamp: UnixTimestamp,
     pub total_commands_executed: u64,
+    /// Sequence number the next `ExecuteCommand` must carry.
+    pub command_sequence: u64,
+    pub last_heartbeat: UnixTimestamp,
+    pub battery_level: Option<u8>,
+    pub health_flags: Option<u32>,
+    /// Seconds without a heartbeat after which the robot is treated as
+    /// offline. Zero disables the check.
+    pub liveness_timeout: i64,
     pub active_operators: Vec<Pubkey>,
     pub max_operators: u8,
+    pub operator_policy: OperatorPolicy,
+    /// Minimum credential level for each `CommandType`, indexed by
+    /// `CommandType::index`.
+    pub command_permissions: [PermissionLevel; CommandType::COUNT],
+    /// Safety officers allowed to trigger the emergency stop in addition
+    /// to the owner, the authority and the active operators.
+    pub guardians: Vec<Pubkey>,
     pub emergency_stop: bool,
+    pub emergency_stop_reason: u16,
+    pub emergency_stopped_by: Pubkey,
+    pub emergency_stopped_at: UnixTimestamp,
+    /// Approvals a `ResumeProposal` needs before `Resume` clears the
+    /// emergency stop. Zero lets the authority resume alone.
+    pub resume_threshold: u8,
+    pub resume_proposal_ttl: i64,
+    /// Approvers for sensitive changes. While `proposal_threshold` is
+    /// non-zero those changes only go through `ExecuteProposal`.
+    pub proposal_approvers: Vec<Pubkey>,
+    pub proposal_threshold: u8,
+    pub proposal_timelock: i64,
+    pub proposal_count: u64,
+    /// Credentials and proposals still open for this robot. Both are
+    /// keyed by the robot's address, so it can only be closed once they
+    /// are gone and a re-initialized robot never inherits them.
+    pub open_credentials: u32,
+    pub open_proposals: u32,
     pub metadata_uri: String,
 }
 
 impl Robot {
-    pub const LEN: usize = 1 + 32 + 32 + 1 + 32 + 32 + 8 + 8 + (32 * 10) + 1 + 1 + 256;
+    pub const SEED_PREFIX: &'static [u8] = b"robot";
+    pub const MAX_OPERATORS: u8 = 10;
+    pub const MAX_GUARDIANS: usize = 8;
+    pub const MAX_PROPOSAL_APPROVERS: usize = 8;
+    pub const MAX_METADATA_URI_LEN: usize = 256;
+    pub const MAX_RECENT_ROOTS: usize = 8;
+    /// Seconds a replaced credential root keeps verifying, unless changed
+    /// with `SetRootGracePeriod`.
+    pub const DEFAULT_ROOT_GRACE_PERIOD: i64 = 300;
+    pub const DEFAULT_RESUME_PROPOSAL_TTL: i64 = 3600;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + 32 + (1 + 32) + (1 + 32)
+        + 32 + 1 + 32 + 32 + 1 + 32
+        + (4 + RetiredRoot::LEN * Self::MAX_RECENT_ROOTS) + 8
+        + 8 + 8 + 8
+        + 8 + (1 + 1) + (1 + 4) + 8
+        + (4 + 32 * Self::MAX_OPERATORS as usize)
+        + 1 + 1 + CommandType::COUNT
+        + (4 + 32 * Self::MAX_GUARDIANS)
+        + 1 + 2 + 32 + 8
+        + 1 + 8
+        + (4 + 32 * Self::MAX_PROPOSAL_APPROVERS) + 1 + 8 + 8
+        + 4 + 4
+        + (4 + Self::MAX_METADATA_URI_LEN);
+    
+    pub fn find_address(program_id: &Pubkey, owner: &Pubkey, robot_id: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, owner.as_ref(), robot_id], program_id)
+    }
     
     pub fn new(
         owner: Pubkey,
         authority: Pubkey,
+        device_key: Pubkey,
         robot_id: [u8; 32],
         merkle_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
         metadata_uri: String,
     ) -> Self {
         Self {
-            is_initialized: true,
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
             owner,
             authority,
+            pending_owner: None,
+            pending_authority: None,
+            device_key,
             status: RobotStatus::Idle,
             robot_id,
             merkle_root,
+            tree_scheme,
+            revocation_root: merkle::EMPTY_LEAF,
+            recent_roots: Vec::new(),
+            root_grace_period: Self::DEFAULT_ROOT_GRACE_PERIOD,
             last_command_timestamp: 0,
             total_commands_executed: 0,
+            command_sequence: 0,
+            last_heartbeat: 0,
+            battery_level: None,
+            health_flags: None,
+            liveness_timeout: 0,
             active_operators: Vec::new(),
-            max_operators: 10,
+            max_operators: Self::MAX_OPERATORS,
+            operator_policy: OperatorPolicy::CredentialOnly,
+            command_permissions: [PermissionLevel::Operator; CommandType::COUNT],
+            guardians: Vec::new(),
             emergency_stop: false,
+            emergency_stop_reason: 0,
+            emergency_stopped_by: Pubkey::default(),
+            emergency_stopped_at: 0,
+            resume_threshold: 0,
+            resume_proposal_ttl: Self::DEFAULT_RESUME_PROPOSAL_TTL,
+            proposal_approvers: Vec::new(),
+            proposal_threshold: 0,
+            proposal_timelock: 0,
+            proposal_count: 0,
+            open_credentials: 0,
+            open_proposals: 0,
             metadata_uri,
         }
     }
+    
+    /// Moves the robot to `next` if the lifecycle allows it, returning the
+    /// previous status. While the emergency stop is engaged the robot can
+    /// not return to an operational status.
+    pub fn transition(&mut self, next: RobotStatus) -> Result<RobotStatus, ProgramError> {
+        if (self.emergency_stop && next.is_operational()) || !self.status.can_transition_to(next) {
+            return Err(RobyError::InvalidRobotState.into());
+        }
+        
+        Ok(std::mem::replace(&mut self.status, next))
+    }
+    
+    pub fn required_permission(&self, command_type: CommandType) -> PermissionLevel {
+        self.command_permissions[command_type.index()]
+    }
+    
+    pub fn can_emergency_stop(&self, key: &Pubkey) -> bool {
+        self.owner == *key
+            || self.authority == *key
+            || self.active_operators.contains(key)
+            || self.guardians.contains(key)
+    }
+    
+    /// Guardians and the robot's administrators (owner and authority) may
+    /// approve resuming after an emergency stop.
+    pub fn is_resume_approver(&self, key: &Pubkey) -> bool {
+        self.owner == *key || self.authority == *key || self.guardians.contains(key)
+    }
+    
+    pub fn resume_approver_count(&self) -> usize {
+        let mut approvers = vec![self.owner, self.authority];
+        approvers.extend(self.guardians.iter().copied());
+        approvers.sort();
+        approvers.dedup();
+        approvers.len()
+    }
+    
+    pub fn is_offline(&self, now: UnixTimestamp) -> bool {
+        self.status == RobotStatus::Offline
+            || (self.liveness_timeout > 0
+                && now > self.last_heartbeat.saturating_add(self.liveness_timeout))
+    }
+    
+    /// Replaces the credential root, keeping the old one in `recent_roots`
+    /// and dropping the oldest entry once the history is full.
+    pub fn rotate_root(
+        &mut self,
+        new_root: [u8; 32],
+        tree_scheme: MerkleTreeScheme,
+        slot: Slot,
+        now: UnixTimestamp,
+    ) {
+        if self.recent_roots.len() >= Self::MAX_RECENT_ROOTS {
+            self.recent_roots.remove(0);
+        }
+        self.recent_roots.push(RetiredRoot {
+            root: self.merkle_root,
+            tree_scheme: self.tree_scheme,
+            retired_slot: slot,
+            retired_at: now,
+        });
+        
+        self.merkle_root = new_root;
+        self.tree_scheme = tree_scheme;
+    }
+    
+    /// The current root followed by every retired root still inside the
+    /// grace period. The grace period is read at verification time, so
+    /// shortening it also cuts off roots that were already retired.
+    pub fn accepted_roots(
+        &self,
+        now: UnixTimestamp,
+    ) -> impl Iterator<Item = ([u8; 32], MerkleTreeScheme)> + '_ {
+        std::iter::once((self.merkle_root, self.tree_scheme)).chain(
+            self.recent_roots
+                .iter()
+                .filter(move |retired| now < retired.retired_at.saturating_add(self.root_grace_period))
+                .map(|retired| (retired.root, retired.tree_scheme)),
+        )
+    }
+}
+
+/// A credential root replaced by `UpdateMerkleRoot`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RetiredRoot {
+    pub root: [u8; 32],
+    pub tree_scheme: MerkleTreeScheme,
+    pub retired_slot: Slot,
+    pub retired_at: UnixTimestamp,
+}
+
+impl RetiredRoot {
+    pub const LEN: usize = 32 + 1 + 8 + 8;
+}
+
+impl RobyAccount for Robot {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"robot\0\0\0";
+    const VERSION: u8 = 1;
 }
 
 impl Sealed for Robot {}
 
 impl IsInitialized for Robot {
     fn is_initialized(&self) -> bool {
-        self.is_initialized
+        self.discriminator == Self::DISCRIMINATOR
+    }
+}
+
+/// Restrictions a credential carries. Sub-credentials issued through
+/// `DelegateCredential` must allow a subset of their parent's commands;
+/// parameter bounds and time windows are checked on every link of the
+/// chain, so a delegate is held to its parents' as well as its own.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CredentialScope {
+    /// `CommandType::bit` mask of commands the holder may execute.
+    pub allowed_commands: u16,
+    pub can_delegate: bool,
+    /// Number of further delegation levels allowed below this credential.
+    pub max_delegation_depth: u8,
+    pub parameter_bounds: Vec<ParameterBound>,
+    /// Weekly windows the credential can be used in; empty means any time.
+    pub time_windows: Vec<TimeWindow>,
+}
+
+impl CredentialScope {
+    pub const MAX_DELEGATION_DEPTH: u8 = 4;
+    pub const MAX_PARAMETER_BOUNDS: usize = 4;
+    pub const MAX_TIME_WINDOWS: usize = 7;
+    pub const LEN: usize = 2 + 1 + 1
+        + 4 + Self::MAX_PARAMETER_BOUNDS * ParameterBound::LEN
+        + 4 + Self::MAX_TIME_WINDOWS * TimeWindow::LEN;
+    
+    pub fn allows(&self, command_type: CommandType) -> bool {
+        self.allowed_commands & command_type.bit() != 0
+    }
+    
+    pub fn is_valid(&self) -> bool {
+        self.allowed_commands & !CommandType::ALL_BITS == 0
+            && self.max_delegation_depth <= Self::MAX_DELEGATION_DEPTH
+            && self.can_delegate == (self.max_delegation_depth > 0)
+            && self.parameter_bounds.len() <= Self::MAX_PARAMETER_BOUNDS
+            && self.parameter_bounds.iter().all(|bound| bound.min <= bound.max)
+            && self.time_windows.len() <= Self::MAX_TIME_WINDOWS
+            && self.time_windows.iter().all(TimeWindow::is_valid)
+    }
+    
+    /// Whether a credential with this scope may be delegated from one
+    /// holding `parent`.
+    pub fn is_within(&self, parent: &CredentialScope) -> bool {
+        parent.can_delegate
+            && self.allowed_commands & !parent.allowed_commands == 0
+            && self.max_delegation_depth < parent.max_delegation_depth
+    }
+    
+    /// Checks a command against the allowed commands, the time windows and
+    /// any parameter bounds set for its type.
+    pub fn authorize(
+        &self,
+        command_type: CommandType,
+        parameters: &[u8],
+        now: UnixTimestamp,
+    ) -> Result<(), ProgramError> {
+        if !self.allows(command_type) {
+            return Err(RobyError::CommandNotInScope.into());
+        }
+        
+        let minute = TimeWindow::minute_of_week(now);
+        if !self.time_windows.is_empty()
+            && !self.time_windows.iter().any(|window| window.contains(minute))
+        {
+            return Err(RobyError::OutsideTimeWindow.into());
+        }
+        
+        let in_bounds = self
+            .parameter_bounds
+            .iter()
+            .filter(|bound| bound.command_type == command_type)
+            .all(|bound| bound.contains(parameters));
+        if !in_bounds {
+            return Err(RobyError::ParameterOutOfBounds.into());
+        }
+        
+        Ok(())
+    }
+}
+
+impl Default for CredentialScope {
+    fn default() -> Self {
+        Self {
+            allowed_commands: CommandType::ALL_BITS,
+            can_delegate: false,
+            max_delegation_depth: 0,
+            parameter_bounds: Vec::new(),
+            time_windows: Vec::new(),
+        }
+    }
+}
+
+/// Little-endian integer layouts a `ParameterBound` can read.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    I64,
+}
+
+impl ParameterKind {
+    pub fn size(&self) -> usize {
+        match self {
+            ParameterKind::U8 | ParameterKind::I8 => 1,
+            ParameterKind::U16 | ParameterKind::I16 => 2,
+            ParameterKind::U32 | ParameterKind::I32 => 4,
+            ParameterKind::I64 => 8,
+        }
+    }
+    
+    /// Reads the value at `offset`, or `None` if `parameters` is too short.
+    pub fn decode(&self, parameters: &[u8], offset: usize) -> Option<i64> {
+        let bytes = parameters.get(offset..offset.checked_add(self.size())?)?;
+        Some(match self {
+            ParameterKind::U8 => bytes[0] as i64,
+            ParameterKind::I8 => bytes[0] as i8 as i64,
+            ParameterKind::U16 => u16::from_le_bytes(bytes.try_into().ok()?) as i64,
+            ParameterKind::I16 => i16::from_le_bytes(bytes.try_into().ok()?) as i64,
+            ParameterKind::U32 => u32::from_le_bytes(bytes.try_into().ok()?) as i64,
+            ParameterKind::I32 => i32::from_le_bytes(bytes.try_into().ok()?) as i64,
+            ParameterKind::I64 => i64::from_le_bytes(bytes.try_into().ok()?),
+        })
+    }
+}
+
+/// Inclusive bounds on a numeric field in the parameters of one command
+/// type, e.g. the speed argument of `Move`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ParameterBound {
+    pub command_type: CommandType,
+    pub offset: u16,
+    pub kind: ParameterKind,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ParameterBound {
+    pub const LEN: usize = 1 + 2 + 1 + 8 + 8;
+    
+    /// Parameters too short to hold the field are out of bounds.
+    pub fn contains(&self, parameters: &[u8]) -> bool {
+        self.kind
+            .decode(parameters, self.offset as usize)
+            .is_some_and(|value| value >= self.min && value <= self.max)
+    }
+}
+
+/// Recurring weekly window in minutes since Monday 00:00 UTC. The end is
+/// exclusive, and a window whose start is after its end wraps past the
+/// end of the week.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start_minute: u16,
+    pub end_minute: u16,
+}
+
+impl TimeWindow {
+    pub const LEN: usize = 2 + 2;
+    pub const MINUTES_PER_WEEK: u16 = 7 * 24 * 60;
+    
+    pub fn minute_of_week(timestamp: UnixTimestamp) -> u16 {
+        // The Unix epoch fell on a Thursday, three days after a Monday.
+        (timestamp.div_euclid(60) + 3 * 24 * 60).rem_euclid(Self::MINUTES_PER_WEEK as i64) as u16
+    }
+    
+    pub fn is_valid(&self) -> bool {
+        self.start_minute < Self::MINUTES_PER_WEEK
+            && self.end_minute <= Self::MINUTES_PER_WEEK
+            && self.start_minute != self.end_minute
+    }
+    
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start_minute < self.end_minute {
+            minute >= self.start_minute && minute < self.end_minute
+        } else {
+            minute >= self.start_minute || minute < self.end_minute
+        }
     }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct Credential {
-    pub is_initialized: bool,
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
     pub owner: Pubkey,
     pub robot: Pubkey,
     pub permission_level: PermissionLevel,
@@ -86,11 +557,34 @@ pub struct Credential {
     pub revoked: bool,
     pub credential_hash: [u8; 32],
     pub issuer: Pubkey,
+    /// Account that paid the rent, refunded when an expired credential is
+    /// closed by someone else.
+    pub payer: Pubkey,
+    /// Credential this one was delegated from, `None` for credentials
+    /// issued by the robot's authority or owner.
+    pub parent_credential: Option<Pubkey>,
+    pub delegation_depth: u8,
+    pub scope: CredentialScope,
 }
 
 impl Credential {
-    pub const LEN: usize = 1 + 32 + 32 + 1 + 8 + 8 + 1 + 32 + 32;
+    pub const SEED_PREFIX: &'static [u8] = b"credential";
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + 32 + 1 + 8 + 8 + 1 + 32 + 32 + 32
+        + 1 + 32 + 1 + CredentialScope::LEN;
+    
+    pub fn find_address(
+        program_id: &Pubkey,
+        robot: &Pubkey,
+        recipient: &Pubkey,
+        credential_hash: &[u8; 32],
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[Self::SEED_PREFIX, robot.as_ref(), recipient.as_ref(), credential_hash],
+            program_id,
+        )
+    }
     
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         owner: Pubkey,
         robot: Pubkey,
@@ -99,9 +593,12 @@ impl Credential {
         valid_until: UnixTimestamp,
         credential_hash: [u8; 32],
         issuer: Pubkey,
+        payer: Pubkey,
+        scope: CredentialScope,
     ) -> Self {
         Self {
-            is_initialized: true,
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
             owner,
             robot,
             permission_level,
@@ -110,9 +607,24 @@ impl Credential {
             revoked: false,
             credential_hash,
             issuer,
+            payer,
+            parent_credential: None,
+            delegation_depth: 0,
+            scope,
         }
     }
     
+    pub fn merkle_leaf(&self) -> [u8; 32] {
+        merkle::credential_leaf(
+            &self.owner,
+            &self.robot,
+            self.permission_level as u8,
+            self.valid_from,
+            self.valid_until,
+            &self.credential_hash,
+        )
+    }
+    
     pub fn is_valid(&self, current_timestamp: UnixTimestamp) -> bool {
         !self.revoked
             && current_timestamp >= self.valid_from
@@ -120,31 +632,96 @@ impl Credential {
     }
 }
 
+impl RobyAccount for Credential {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"credentl";
+    const VERSION: u8 = 1;
+}
+
 impl Sealed for Credential {}
 
 impl IsInitialized for Credential {
     fn is_initialized(&self) -> bool {
-        self.is_initialized
+        self.discriminator == Self::DISCRIMINATOR
     }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct CommandLog {
-    pub is_initialized: bool,
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
     pub robot: Pubkey,
     pub executor: Pubkey,
     pub command_type: CommandType,
     pub timestamp: UnixTimestamp,
+    pub sequence: u64,
     pub parameters: Vec<u8>,
+    pub status: CommandStatus,
     pub success: bool,
     pub error_code: u32,
 }
 
 impl CommandLog {
-    pub const LEN: usize = 1 + 32 + 32 + 1 + 8 + 256 + 1 + 4;
+    pub const MAX_PARAMETERS_LEN: usize = 256;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + 32 + 1 + 8 + 8
+        + (4 + Self::MAX_PARAMETERS_LEN)
+        + 1 + 1 + 4;
+    
+    pub fn new(
+        robot: Pubkey,
+        executor: Pubkey,
+        command_type: CommandType,
+        timestamp: UnixTimestamp,
+        sequence: u64,
+        parameters: Vec<u8>,
+    ) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            robot,
+            executor,
+            command_type,
+            timestamp,
+            sequence,
+            parameters,
+            status: CommandStatus::Queued,
+            success: false,
+            error_code: 0,
+        }
+    }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+impl RobyAccount for CommandLog {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"cmdlog\0\0";
+    const VERSION: u8 = 1;
+}
+
+/// Progress of a command as reported by the robot.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CommandStatus {
+    Queued,
+    Started,
+    Completed,
+    Failed,
+}
+
+impl CommandStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, CommandStatus::Completed | CommandStatus::Failed)
+    }
+    
+    pub fn can_transition_to(&self, next: CommandStatus) -> bool {
+        matches!(
+            (self, next),
+            (CommandStatus::Queued, CommandStatus::Started)
+                | (CommandStatus::Queued, CommandStatus::Completed)
+                | (CommandStatus::Queued, CommandStatus::Failed)
+                | (CommandStatus::Started, CommandStatus::Completed)
+                | (CommandStatus::Started, CommandStatus::Failed)
+        )
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
 pub enum CommandType {
     Move,
     Rotate,
@@ -157,6 +734,561 @@ pub enum CommandType {
     Custom,
 }
 
+impl CommandType {
+    pub const COUNT: usize = 9;
+    pub const ALL_BITS: u16 = (1 << Self::COUNT) - 1;
+    
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+    
+    pub fn bit(&self) -> u16 {
+        1 << self.index()
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct CredentialTree {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub robot: Pubkey,
+    pub tree: IncrementalMerkleTree,
+}
+
+impl CredentialTree {
+    pub const SEED_PREFIX: &'static [u8] = b"credential_tree";
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + IncrementalMerkleTree::LEN;
+    
+    pub fn find_address(program_id: &Pubkey, robot: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, robot.as_ref()], program_id)
+    }
+    
+    pub fn new(robot: Pubkey, tree: IncrementalMerkleTree) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            robot,
+            tree,
+        }
+    }
+}
+
+impl RobyAccount for CredentialTree {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"credtree";
+    const VERSION: u8 = 1;
+}
+
+/// Fixed-capacity ring buffer of the commands issued to a robot.
+///
+/// A command occupies slot `sequence % CAPACITY`, and a slot can only be
+/// reused once the robot has acknowledged its previous command as finished.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct CommandQueue {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub robot: Pubkey,
+    pub entries: Vec<Option<QueuedCommand>>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct QueuedCommand {
+    pub sequence: u64,
+    pub command_log: Pubkey,
+    pub status: CommandStatus,
+    pub enqueued_at: UnixTimestamp,
+    pub updated_at: UnixTimestamp,
+}
+
+impl QueuedCommand {
+    pub const LEN: usize = 8 + 32 + 1 + 8 + 8;
+}
+
+impl CommandQueue {
+    pub const SEED_PREFIX: &'static [u8] = b"command_queue";
+    pub const CAPACITY: usize = 16;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + (4 + (1 + QueuedCommand::LEN) * Self::CAPACITY);
+    
+    pub fn find_address(program_id: &Pubkey, robot: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, robot.as_ref()], program_id)
+    }
+    
+    pub fn new(robot: Pubkey) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            robot,
+            entries: vec![None; Self::CAPACITY],
+        }
+    }
+    
+    /// Adds a command, failing if its slot still holds an unfinished one.
+    pub fn enqueue(&mut self, command: QueuedCommand) -> Result<(), ProgramError> {
+        let slot = (command.sequence % Self::CAPACITY as u64) as usize;
+        
+        let entry = self.entries.get_mut(slot).ok_or(RobyError::InvalidAccountData)?;
+        
+        if matches!(entry, Some(existing) if !existing.status.is_finished()) {
+            return Err(RobyError::CommandQueueFull.into());
+        }
+        
+        *entry = Some(command);
+        Ok(())
+    }
+    
+    pub fn get_mut(&mut self, sequence: u64) -> Option<&mut QueuedCommand> {
+        let slot = (sequence % Self::CAPACITY as u64) as usize;
+        self.entries
+            .get_mut(slot)?
+            .as_mut()
+            .filter(|entry| entry.sequence == sequence)
+    }
+    
+    pub fn has_started_commands(&self) -> bool {
+        self.entries
+            .iter()
+            .flatten()
+            .any(|entry| entry.status == CommandStatus::Started)
+    }
+}
+
+impl RobyAccount for CommandQueue {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"cmdqueue";
+    const VERSION: u8 = 1;
+}
+
+/// Pending request to clear a robot's emergency stop, collecting approvals
+/// until `Resume` consumes it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ResumeProposal {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub robot: Pubkey,
+    pub proposer: Pubkey,
+    /// Account that paid the rent and gets it back when `Resume` closes
+    /// the proposal; the proposer unless a separate payer funded it.
+    pub payer: Pubkey,
+    pub created_at: UnixTimestamp,
+    pub expires_at: UnixTimestamp,
+    pub approvals: Vec<Pubkey>,
+}
+
+impl ResumeProposal {
+    pub const SEED_PREFIX: &'static [u8] = b"resume_proposal";
+    pub const MAX_APPROVALS: usize = Robot::MAX_GUARDIANS + 2;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + 32 + 32 + 8 + 8 + (4 + 32 * Self::MAX_APPROVALS);
+    
+    pub fn find_address(program_id: &Pubkey, robot: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, robot.as_ref()], program_id)
+    }
+    
+    pub fn new(robot: Pubkey, proposer: Pubkey, created_at: UnixTimestamp, ttl: i64) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            robot,
+            proposer,
+            payer: proposer,
+            created_at,
+            expires_at: created_at.saturating_add(ttl),
+            approvals: vec![proposer],
+        }
+    }
+    
+    /// A proposal only counts for the stop that was active when it was
+    /// created and until it expires.
+    pub fn is_live(&self, robot: &Robot, now: UnixTimestamp) -> bool {
+        robot.emergency_stop && self.created_at >= robot.emergency_stopped_at && now < self.expires_at
+    }
+    
+    /// Approvals from keys that are still eligible on the robot.
+    pub fn approval_count(&self, robot: &Robot) -> usize {
+        self.approvals
+            .iter()
+            .filter(|approver| robot.is_resume_approver(approver))
+            .count()
+    }
+}
+
+impl RobyAccount for ResumeProposal {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"resumepr";
+    const VERSION: u8 = 1;
+}
+
+/// M-of-N signer set that can stand in for a robot's owner or authority.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct MultisigAuthority {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+impl MultisigAuthority {
+    pub const SEED_PREFIX: &'static [u8] = b"multisig";
+    pub const MAX_SIGNERS: usize = 11;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 1 + (4 + 32 * Self::MAX_SIGNERS);
+    
+    pub fn find_address(program_id: &Pubkey, creator: &Pubkey, multisig_id: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, creator.as_ref(), multisig_id], program_id)
+    }
+    
+    pub fn new(signers: Vec<Pubkey>, threshold: u8) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            threshold,
+            signers,
+        }
+    }
+}
+
+impl RobyAccount for MultisigAuthority {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"multisig";
+    const VERSION: u8 = 1;
+}
+
+/// A sensitive robot change waiting for approvals and its timelock. The
+/// change itself is kept as a packed `RobyInstruction`; the account is
+/// closed once the proposal is executed or cancelled.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct Proposal {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub robot: Pubkey,
+    pub proposer: Pubkey,
+    /// Account refunded when the proposal is executed or cancelled; the
+    /// proposer unless a separate payer funded it.
+    pub payer: Pubkey,
+    pub id: u64,
+    pub instruction_data: Vec<u8>,
+    pub approvals: Vec<Pubkey>,
+    pub created_at: UnixTimestamp,
+    /// When the approval threshold was first reached; zero until then.
+    pub approved_at: UnixTimestamp,
+}
+
+impl Proposal {
+    pub const SEED_PREFIX: &'static [u8] = b"proposal";
+    pub const MAX_INSTRUCTION_LEN: usize = 512;
+    /// Every configured approver plus the owner.
+    pub const MAX_APPROVALS: usize = Robot::MAX_PROPOSAL_APPROVERS + 1;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 1 + 32 + 32 + 32 + 8
+        + (4 + Self::MAX_INSTRUCTION_LEN)
+        + (4 + 32 * Self::MAX_APPROVALS)
+        + 8 + 8;
+    
+    pub fn find_address(program_id: &Pubkey, robot: &Pubkey, id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[Self::SEED_PREFIX, robot.as_ref(), &id.to_le_bytes()],
+            program_id,
+        )
+    }
+    
+    pub fn new(
+        robot: Pubkey,
+        proposer: Pubkey,
+        id: u64,
+        instruction_data: Vec<u8>,
+        created_at: UnixTimestamp,
+    ) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            robot,
+            proposer,
+            payer: proposer,
+            id,
+            instruction_data,
+            approvals: Vec::new(),
+            created_at,
+            approved_at: 0,
+        }
+    }
+    
+    /// Approvals from keys that are still approvers on the robot.
+    pub fn approval_count(&self, robot: &Robot) -> usize {
+        self.approvals
+            .iter()
+            .filter(|approver| robot.proposal_approvers.contains(approver))
+            .count()
+    }
+    
+    /// Whether enough approvers have signed off. A robot without a
+    /// threshold has no approvers to speak for it, so nothing is approved.
+    pub fn is_approved(&self, robot: &Robot) -> bool {
+        robot.proposal_threshold > 0 && self.approval_count(robot) >= robot.proposal_threshold as usize
+    }
+    
+    /// Records an approval, starting the timelock once the threshold is met.
+    pub fn approve(&mut self, robot: &Robot, approver: Pubkey, now: UnixTimestamp) {
+        self.approvals.push(approver);
+        if self.approved_at == 0 && self.is_approved(robot) {
+            self.approved_at = now;
+        }
+    }
+    
+    pub fn is_executable(&self, robot: &Robot, now: UnixTimestamp) -> bool {
+        self.approved_at != 0
+            && self.is_approved(robot)
+            && now >= self.approved_at.saturating_add(robot.proposal_timelock)
+    }
+}
+
+impl RobyAccount for Proposal {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = *b"proposal";
+    const VERSION: u8 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const ALL_STATUSES: [RobotStatus; 6] = [
+        RobotStatus::Offline,
+        RobotStatus::Idle,
+        RobotStatus::Active,
+        RobotStatus::Executing,
+        RobotStatus::Error,
+        RobotStatus::Maintenance,
+    ];
+    
+    fn robot_with_status(status: RobotStatus) -> Robot {
+        let mut robot = Robot::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            [1u8; 32],
+            [0u8; 32],
+            MerkleTreeScheme::SortedPair,
+            String::new(),
+        );
+        robot.status = status;
+        robot
+    }
+    
+    #[test]
+    fn test_status_transition_table() {
+        for from in ALL_STATUSES {
+            assert!(from.can_transition_to(from));
+            assert!(from.can_transition_to(RobotStatus::Error));
+        }
+        
+        assert!(RobotStatus::Idle.can_transition_to(RobotStatus::Maintenance));
+        assert!(RobotStatus::Error.can_transition_to(RobotStatus::Maintenance));
+        assert!(!RobotStatus::Active.can_transition_to(RobotStatus::Maintenance));
+        assert!(!RobotStatus::Executing.can_transition_to(RobotStatus::Maintenance));
+        
+        assert!(!RobotStatus::Error.can_transition_to(RobotStatus::Executing));
+        assert!(!RobotStatus::Offline.can_transition_to(RobotStatus::Executing));
+        assert!(!RobotStatus::Maintenance.can_transition_to(RobotStatus::Executing));
+        assert!(!RobotStatus::Offline.can_transition_to(RobotStatus::Active));
+    }
+    
+    #[test]
+    fn test_emergency_stop_blocks_operational_statuses() {
+        let mut robot = robot_with_status(RobotStatus::Error);
+        robot.emergency_stop = true;
+        
+        assert!(robot.transition(RobotStatus::Idle).is_err());
+        assert_eq!(robot.transition(RobotStatus::Maintenance).unwrap(), RobotStatus::Error);
+        assert_eq!(robot.status, RobotStatus::Maintenance);
+        
+        robot.emergency_stop = false;
+        assert_eq!(robot.transition(RobotStatus::Idle).unwrap(), RobotStatus::Maintenance);
+        assert!(robot.transition(RobotStatus::Executing).is_ok());
+    }
+    
+    #[test]
+    fn test_delegated_scope_must_narrow() {
+        let parent = CredentialScope {
+            allowed_commands: CommandType::Move.bit() | CommandType::Rotate.bit(),
+            can_delegate: true,
+            max_delegation_depth: 2,
+            ..CredentialScope::default()
+        };
+        assert!(parent.is_valid());
+        
+        let child = CredentialScope {
+            allowed_commands: CommandType::Move.bit(),
+            can_delegate: true,
+            max_delegation_depth: 1,
+            ..CredentialScope::default()
+        };
+        assert!(child.is_within(&parent));
+        assert!(child.allows(CommandType::Move));
+        assert!(!child.allows(CommandType::Rotate));
+        
+        let wider = CredentialScope {
+            allowed_commands: child.allowed_commands | CommandType::Grab.bit(),
+            ..child.clone()
+        };
+        assert!(!wider.is_within(&parent));
+        
+        let deeper = CredentialScope { max_delegation_depth: 2, ..child.clone() };
+        assert!(!deeper.is_within(&parent));
+        
+        let leaf = CredentialScope::default();
+        assert!(leaf.is_valid());
+        assert!(!CredentialScope { allowed_commands: CommandType::Move.bit(), ..leaf.clone() }
+            .is_within(&leaf));
+    }
+    
+    #[test]
+    fn test_scope_time_windows_and_parameter_bounds() {
+        // 2024-01-01 00:00 UTC was a Monday.
+        let monday = 1_704_067_200;
+        assert_eq!(TimeWindow::minute_of_week(monday), 0);
+        assert_eq!(TimeWindow::minute_of_week(monday - 60), TimeWindow::MINUTES_PER_WEEK - 1);
+        
+        // Monday 09:00-17:00 and a Sunday night shift into Monday.
+        let scope = CredentialScope {
+            parameter_bounds: vec![ParameterBound {
+                command_type: CommandType::Move,
+                offset: 2,
+                kind: ParameterKind::I16,
+                min: -100,
+                max: 100,
+            }],
+            time_windows: vec![
+                TimeWindow { start_minute: 9 * 60, end_minute: 17 * 60 },
+                TimeWindow { start_minute: 6 * 1440 + 22 * 60, end_minute: 6 * 60 },
+            ],
+            ..CredentialScope::default()
+        };
+        assert!(scope.is_valid());
+        
+        let in_range = [0u8, 0, 0x9c, 0xff]; // -100
+        let out_of_range = [0u8, 0, 0x65, 0x00]; // 101
+        let shift = monday + 10 * 3600;
+        
+        assert!(scope.authorize(CommandType::Move, &in_range, shift).is_ok());
+        assert!(scope.authorize(CommandType::Move, &out_of_range, shift).is_err());
+        assert!(scope.authorize(CommandType::Move, &in_range[..3], shift).is_err());
+        assert!(scope.authorize(CommandType::Rotate, &out_of_range, shift).is_ok());
+        
+        assert!(scope.authorize(CommandType::Rotate, &[], monday + 2 * 3600).is_ok());
+        assert!(scope.authorize(CommandType::Rotate, &[], monday - 3600).is_ok());
+        assert!(scope.authorize(CommandType::Rotate, &[], monday + 8 * 3600).is_err());
+        assert!(scope.authorize(CommandType::Rotate, &[], monday + 17 * 3600).is_err());
+    }
+    
+    #[test]
+    fn test_proposal_waits_for_threshold_and_timelock() {
+        let mut robot = robot_with_status(RobotStatus::Idle);
+        let approvers = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        robot.proposal_approvers = approvers.to_vec();
+        robot.proposal_threshold = 2;
+        robot.proposal_timelock = 100;
+        
+        let mut proposal = Proposal::new(Pubkey::new_unique(), approvers[0], 0, vec![0], 10);
+        proposal.approve(&robot, approvers[0], 10);
+        proposal.approve(&robot, Pubkey::new_unique(), 20);
+        assert_eq!(proposal.approval_count(&robot), 1);
+        assert!(!proposal.is_executable(&robot, 1_000));
+        
+        proposal.approve(&robot, approvers[1], 50);
+        assert_eq!(proposal.approved_at, 50);
+        assert!(!proposal.is_executable(&robot, 149));
+        assert!(proposal.is_executable(&robot, 150));
+        
+        // Removing an approver drops its approval below the threshold again.
+        robot.proposal_approvers.retain(|approver| *approver != approvers[1]);
+        assert!(!proposal.is_executable(&robot, 150));
+    }
+    
+    #[test]
+    fn test_resume_proposal_only_lives_for_its_stop() {
+        let mut robot = robot_with_status(RobotStatus::Error);
+        robot.emergency_stop = true;
+        robot.emergency_stopped_at = 100;
+        
+        let proposal = ResumeProposal::new(Pubkey::new_unique(), robot.authority, 150, 60);
+        assert!(proposal.is_live(&robot, 150));
+        assert!(proposal.is_live(&robot, 209));
+        assert!(!proposal.is_live(&robot, 210));
+        
+        let stale = ResumeProposal::new(Pubkey::new_unique(), robot.authority, 50, 600);
+        assert!(!stale.is_live(&robot, 150));
+        
+        robot.emergency_stop = false;
+        assert!(!proposal.is_live(&robot, 150));
+    }
+    
+    #[test]
+    fn test_proposals_need_a_threshold() {
+        let mut robot = robot_with_status(RobotStatus::Idle);
+        let approver = Pubkey::new_unique();
+        robot.proposal_approvers = vec![approver];
+        
+        let mut proposal = Proposal::new(Pubkey::new_unique(), robot.owner, 0, vec![0], 10);
+        proposal.approve(&robot, robot.owner, 10);
+        proposal.approve(&robot, approver, 10);
+        assert_eq!(proposal.approved_at, 0);
+        assert!(!proposal.is_executable(&robot, 10));
+        
+        robot.proposal_threshold = 1;
+        assert!(!proposal.is_executable(&robot, 10));
+        
+        let mut proposal = Proposal::new(Pubkey::new_unique(), approver, 1, vec![0], 10);
+        proposal.approve(&robot, approver, 10);
+        assert!(proposal.is_executable(&robot, 10));
+    }
+    
+    #[test]
+    fn test_retired_roots_verify_for_the_grace_period() {
+        let mut robot = robot_with_status(RobotStatus::Idle);
+        robot.root_grace_period = 60;
+        let old_root = robot.merkle_root;
+        robot.rotate_root([2u8; 32], MerkleTreeScheme::SortedPair, 5, 100);
+        
+        let roots = |robot: &Robot, now| robot.accepted_roots(now).map(|(root, _)| root).collect::<Vec<_>>();
+        assert_eq!(roots(&robot, 159), vec![[2u8; 32], old_root]);
+        assert_eq!(roots(&robot, 160), vec![[2u8; 32]]);
+        
+        for i in 0..Robot::MAX_RECENT_ROOTS as u8 {
+            robot.rotate_root([3 + i; 32], MerkleTreeScheme::SortedPair, 6, 100);
+        }
+        assert_eq!(robot.recent_roots.len(), Robot::MAX_RECENT_ROOTS);
+        assert_eq!(robot.recent_roots[0].root, [2u8; 32]);
+        assert!(!roots(&robot, 100).contains(&old_root));
+        
+        robot.root_grace_period = 0;
+        assert_eq!(roots(&robot, 100), vec![robot.merkle_root]);
+    }
+    
+    #[test]
+    fn test_missed_heartbeats_take_the_robot_offline() {
+        let mut robot = robot_with_status(RobotStatus::Active);
+        robot.last_heartbeat = 100;
+        assert!(!robot.is_offline(i64::MAX));
+        
+        robot.liveness_timeout = 30;
+        assert!(!robot.is_offline(130));
+        assert!(robot.is_offline(131));
+        
+        robot.last_heartbeat = i64::MAX - 10;
+        assert!(!robot.is_offline(i64::MAX));
+        
+        assert!(robot_with_status(RobotStatus::Offline).is_offline(0));
+    }
+    
+    #[test]
+    fn test_emergency_stop_is_limited_to_known_keys() {
+        let mut robot = robot_with_status(RobotStatus::Active);
+        let (operator, guardian) = (Pubkey::new_unique(), Pubkey::new_unique());
+        robot.active_operators.push(operator);
+        robot.guardians.push(guardian);
+        
+        for key in [robot.owner, robot.authority, operator, guardian] {
+            assert!(robot.can_emergency_stop(&key));
+        }
+        assert!(!robot.can_emergency_stop(&robot.device_key));
+        assert!(!robot.can_emergency_stop(&Pubkey::new_unique()));
+    }
+}
+
+
+
 
 
 
@@ -0,0 +1,77 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    system_program,
+};
+
+use crate::error::RobyError;
+
+/// Creates a program-owned PDA of `space` bytes funded by `payer`.
+///
+/// Handles addresses that were pre-funded by a third party, where a plain
+/// `create_account` would fail and could be used to block initialization.
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    new_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(space);
+
+    if new_account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                new_account.key,
+                required_lamports,
+                space as u64,
+                program_id,
+            ),
+            &[payer.clone(), new_account.clone(), system_program.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let top_up = required_lamports.saturating_sub(new_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, new_account.key, top_up),
+            &[payer.clone(), new_account.clone(), system_program.clone()],
+        )?;
+    }
+
+    invoke_signed(
+        &system_instruction::allocate(new_account.key, space as u64),
+        &[new_account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+
+    invoke_signed(
+        &system_instruction::assign(new_account.key, program_id),
+        &[new_account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Moves all lamports from a program-owned account to `receiver` and hands
+/// the emptied account back to the system program.
+pub fn close_account(account: &AccountInfo, receiver: &AccountInfo) -> ProgramResult {
+    let receiver_lamports = receiver
+        .lamports()
+        .checked_add(account.lamports())
+        .ok_or(RobyError::ArithmeticOverflow)?;
+
+    **receiver.lamports.borrow_mut() = receiver_lamports;
+    **account.lamports.borrow_mut() = 0;
+
+    account.data.borrow_mut().fill(0);
+    account.assign(&system_program::id());
+    account.realloc(0, false)
+}
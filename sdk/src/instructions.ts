@@ -1,278 +1,1173 @@
 import {
+  AccountMeta,
   PublicKey,
   TransactionInstruction,
   SystemProgram,
   SYSVAR_RENT_PUBKEY,
   SYSVAR_CLOCK_PUBKEY,
 } from '@solana/web3.js';
-import { serialize } from 'borsh';
 import {
   InitializeRobotParams,
   IssueCredentialParams,
+  DelegateCredentialParams,
   ExecuteCommandParams,
-  PermissionLevel,
+  Authorization,
+  CredentialProof,
+  CredentialScope,
+  CredentialMultiProof,
+  SparseMerkleMultiProof,
+  SparseMerkleProof,
+  DEFAULT_CREDENTIAL_SCOPE,
+  AccountType,
+  CommandStatus,
   CommandType,
+  MerkleTreeScheme,
+  OperatorPolicy,
+  PermissionLevel,
+  RobotStatus,
 } from './types';
+import {
+  deriveRobotAddress,
+  deriveCredentialAddress,
+  deriveCredentialTreeAddress,
+  deriveCommandQueueAddress,
+  deriveResumeProposalAddress,
+  deriveMultisigAddress,
+  deriveProposalAddress,
+} from './utils';
+
+/** Variant indices of the program's `RobyInstruction`, in declaration order. */
+export enum RobyInstructionKind {
+  InitializeRobot = 0,
+  IssueCredential = 1,
+  RevokeCredential = 2,
+  ExecuteCommand = 3,
+  UpdateMerkleRoot = 4,
+  TransferAuthority = 5,
+  AddOperator = 6,
+  RemoveOperator = 7,
+  EmergencyStop = 8,
+  Resume = 9,
+  UpdateRobotStatus = 10,
+  TransferOwnership = 11,
+  CloseRobot = 12,
+  CloseCredential = 13,
+  CloseCommandLog = 14,
+  MigrateAccount = 15,
+  VerifyCredentials = 16,
+  InitializeCredentialTree = 17,
+  UpdateRevocationRoot = 18,
+  SetRootGracePeriod = 19,
+  InitializeCommandQueue = 20,
+  AcknowledgeCommand = 21,
+  RotateDeviceKey = 22,
+  ReportStatus = 23,
+  Heartbeat = 24,
+  SetLivenessTimeout = 25,
+  AddGuardian = 26,
+  RemoveGuardian = 27,
+  SetResumePolicy = 28,
+  ProposeResume = 29,
+  ApproveResume = 30,
+  InitializeMultisig = 31,
+  ConfigureProposals = 32,
+  CreateProposal = 33,
+  ApproveProposal = 34,
+  CancelProposal = 35,
+  ExecuteProposal = 36,
+  AcceptOwnership = 37,
+  AcceptAuthority = 38,
+  CancelTransfer = 39,
+  SetOperatorPolicy = 40,
+  SetCommandPermission = 41,
+  DelegateCredential = 42,
+  FailCommand = 43,
+}
 
 export class RobyInstructions {
   constructor(private programId: PublicKey) {}
 
   createInitializeRobotInstruction(
-    params: InitializeRobotParams,
-    robotAccount: PublicKey
+    params: InitializeRobotParams
   ): TransactionInstruction {
+    const [robotAccount] = deriveRobotAddress(
+      this.programId,
+      params.owner,
+      params.robotId
+    );
     const data = Buffer.concat([
-      Buffer.from([0]),
+      Buffer.from([RobyInstructionKind.InitializeRobot]),
       params.robotId,
       params.merkleRoot,
-      Buffer.from(this.encodeString(params.metadataUri)),
+      Buffer.from([params.treeScheme ?? MerkleTreeScheme.SortedPair]),
+      this.encodeString(params.metadataUri),
+      params.deviceKey.toBuffer(),
     ]);
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: params.owner, isSigner: true, isWritable: false },
+        { pubkey: params.owner, isSigner: true, isWritable: true },
         { pubkey: params.authority, isSigner: false, isWritable: false },
         { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
       ],
-      programId: this.programId,
-      data,
-    });
+      data
+    );
   }
 
   createIssueCredentialInstruction(
     params: IssueCredentialParams,
-    credentialAccount: PublicKey,
-    robotAccount: PublicKey
+    authorization: Authorization = {}
   ): TransactionInstruction {
+    const [credentialAccount] = deriveCredentialAddress(
+      this.programId,
+      params.robot,
+      params.recipient,
+      params.credentialHash
+    );
     const data = Buffer.concat([
-      Buffer.from([1]),
-      Buffer.from([params.permissionLevel]),
+      Buffer.from([RobyInstructionKind.IssueCredential, params.permissionLevel]),
       this.encodeI64(params.validFrom),
       this.encodeI64(params.validUntil),
       params.credentialHash,
+      this.encodeScope(params.scope ?? DEFAULT_CREDENTIAL_SCOPE),
+      this.encodeBool(params.appendToTree ?? false),
+      this.encodeOption(authorization.adminProof, (proof) => this.encodeProof(proof)),
     ]);
 
-    return new TransactionInstruction({
-      keys: [
-        { pubkey: credentialAccount, isSigner: false, isWritable: true },
-        { pubkey: robotAccount, isSigner: false, isWritable: false },
-        { pubkey: params.issuer, isSigner: true, isWritable: false },
-        { pubkey: params.recipient, isSigner: false, isWritable: false },
-        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
-      ],
-      programId: this.programId,
-      data,
-    });
+    const keys: AccountMeta[] = [
+      { pubkey: credentialAccount, isSigner: false, isWritable: true },
+      { pubkey: params.robot, isSigner: false, isWritable: true },
+      this.authorityKey(params.issuer, authorization),
+      { pubkey: params.payer ?? params.issuer, isSigner: true, isWritable: true },
+      { pubkey: params.recipient, isSigner: false, isWritable: false },
+      { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ];
+    if (params.appendToTree) {
+      const [treeAccount] = deriveCredentialTreeAddress(this.programId, params.robot);
+      keys.push({ pubkey: treeAccount, isSigner: false, isWritable: true });
+    }
+
+    return this.instruction([...keys, ...this.trailingKeys(authorization)], data);
   }
 
   createRevokeCredentialInstruction(
     credentialAccount: PublicKey,
     robotAccount: PublicKey,
-    authority: PublicKey
+    authority: PublicKey,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.from([2]);
+    const data = Buffer.concat([
+      Buffer.from([RobyInstructionKind.RevokeCredential]),
+      this.encodeOption(authorization.adminProof, (proof) => this.encodeProof(proof)),
+    ]);
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [
         { pubkey: credentialAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+        this.authorityKey(authority, authorization),
         { pubkey: robotAccount, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      data
+    );
   }
 
+  /**
+   * The command log must be a fresh, zeroed account of
+   * `CommandLogAccount.LEN` bytes owned by the program.
+   */
   createExecuteCommandInstruction(
     params: ExecuteCommandParams,
     commandLogAccount: PublicKey
   ): TransactionInstruction {
-    const proofData = Buffer.concat(
-      params.merkleProof.map((p) => Buffer.from(p))
-    );
-
+    const [commandQueue] = deriveCommandQueueAddress(this.programId, params.robot);
     const data = Buffer.concat([
-      Buffer.from([3]),
-      Buffer.from([params.commandType]),
+      Buffer.from([RobyInstructionKind.ExecuteCommand, params.commandType]),
       this.encodeVec(params.parameters),
-      this.encodeVec(proofData),
+      this.encodeProof(params.proof),
+      this.encodeU64(params.expectedSequence),
     ]);
 
-    return new TransactionInstruction({
-      keys: [
-        { pubkey: params.robot, isSigner: false, isWritable: true },
-        { pubkey: params.executor, isSigner: true, isWritable: false },
-        { pubkey: params.credential, isSigner: false, isWritable: false },
-        { pubkey: commandLogAccount, isSigner: false, isWritable: true },
-        { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
-      ],
-      programId: this.programId,
-      data,
-    });
+    const keys: AccountMeta[] = [
+      { pubkey: params.robot, isSigner: false, isWritable: true },
+      { pubkey: params.executor, isSigner: true, isWritable: false },
+      { pubkey: params.credential, isSigner: false, isWritable: false },
+      { pubkey: commandLogAccount, isSigner: false, isWritable: true },
+      { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+      { pubkey: commandQueue, isSigner: false, isWritable: true },
+      ...this.readonlyKeys(params.parentCredentials ?? []),
+    ];
+    if (params.credentialTree) {
+      keys.push({ pubkey: params.credentialTree, isSigner: false, isWritable: false });
+    }
+
+    return this.instruction(keys, data);
   }
 
   createUpdateMerkleRootInstruction(
     robotAccount: PublicKey,
     authority: PublicKey,
-    newMerkleRoot: Buffer
+    newMerkleRoot: Buffer,
+    treeScheme: MerkleTreeScheme = MerkleTreeScheme.SortedPair,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.concat([Buffer.from([4]), newMerkleRoot]);
+    const data = Buffer.concat([
+      Buffer.from([RobyInstructionKind.UpdateMerkleRoot]),
+      newMerkleRoot,
+      Buffer.from([treeScheme]),
+    ]);
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+        this.authorityKey(authority, authorization),
+        { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      data
+    );
   }
 
+  /** Names a pending authority, who must accept with `AcceptAuthority`. */
   createTransferAuthorityInstruction(
     robotAccount: PublicKey,
     currentAuthority: PublicKey,
-    newAuthority: PublicKey
+    newAuthority: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      currentAuthority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.TransferAuthority]),
+        newAuthority.toBuffer(),
+      ]),
+      authorization
+    );
+  }
+
+  createAddOperatorInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    operator: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.AddOperator]),
+        operator.toBuffer(),
+        this.encodeOption(authorization.adminProof, (proof) => this.encodeProof(proof)),
+      ]),
+      authorization
+    );
+  }
+
+  createRemoveOperatorInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    operator: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.RemoveOperator]),
+        operator.toBuffer(),
+        this.encodeOption(authorization.adminProof, (proof) => this.encodeProof(proof)),
+      ]),
+      authorization
+    );
+  }
+
+  /** The stopper must be the owner, the authority, an operator or a guardian. */
+  createEmergencyStopInstruction(
+    robotAccount: PublicKey,
+    stopper: PublicKey,
+    reasonCode: number = 0
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      stopper,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.EmergencyStop]),
+        this.encodeU16(reasonCode),
+      ])
+    );
+  }
+
+  /**
+   * While the robot has a resume policy, the approved resume proposal and
+   * the payer it refunds must be passed in `resumeProposalPayer`.
+   */
+  createResumeInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    resumeProposalPayer?: PublicKey,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.concat([Buffer.from([5]), newAuthority.toBuffer()]);
+    const keys: AccountMeta[] = [
+      { pubkey: robotAccount, isSigner: false, isWritable: true },
+      this.authorityKey(authority, authorization),
+    ];
+    if (resumeProposalPayer) {
+      const [proposal] = deriveResumeProposalAddress(this.programId, robotAccount);
+      keys.push(
+        { pubkey: proposal, isSigner: false, isWritable: true },
+        { pubkey: resumeProposalPayer, isSigner: false, isWritable: true }
+      );
+    }
+
+    return this.instruction(
+      [...keys, ...this.trailingKeys(authorization)],
+      Buffer.from([RobyInstructionKind.Resume])
+    );
+  }
+
+  /** `Executing` can only be reached through `AcknowledgeCommand`. */
+  createUpdateRobotStatusInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    status: RobotStatus,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.UpdateRobotStatus, status]),
+        this.encodeOption(authorization.adminProof, (proof) => this.encodeProof(proof)),
+      ]),
+      authorization
+    );
+  }
+
+  /** Names a pending owner, who must accept with `AcceptOwnership`. */
+  createTransferOwnershipInstruction(
+    robotAccount: PublicKey,
+    currentOwner: PublicKey,
+    newOwner: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      currentOwner,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.TransferOwnership]),
+        newOwner.toBuffer(),
+      ]),
+      authorization
+    );
+  }
+
+  /**
+   * Closes the robot with its command queue, credential tree and resume
+   * proposal. A pending resume proposal is refunded to its payer, which
+   * must then be given as `resumeProposalPayer`.
+   */
+  createCloseRobotInstruction(
+    robotAccount: PublicKey,
+    owner: PublicKey,
+    receiver: PublicKey,
+    resumeProposalPayer?: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [commandQueue] = deriveCommandQueueAddress(this.programId, robotAccount);
+    const [credentialTree] = deriveCredentialTreeAddress(this.programId, robotAccount);
+    const [resumeProposal] = deriveResumeProposalAddress(this.programId, robotAccount);
+
+    const keys: AccountMeta[] = [
+      { pubkey: robotAccount, isSigner: false, isWritable: true },
+      this.authorityKey(owner, authorization),
+      { pubkey: receiver, isSigner: false, isWritable: true },
+      { pubkey: commandQueue, isSigner: false, isWritable: true },
+      { pubkey: credentialTree, isSigner: false, isWritable: true },
+      { pubkey: resumeProposal, isSigner: false, isWritable: true },
+    ];
+    if (resumeProposalPayer) {
+      keys.push({ pubkey: resumeProposalPayer, isSigner: false, isWritable: true });
+    }
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [...keys, ...this.trailingKeys(authorization)],
+      Buffer.from([RobyInstructionKind.CloseRobot])
+    );
+  }
+
+  /**
+   * The issuer or the robot's authority may close a credential. Anyone may
+   * close an expired one, but only to refund the account that paid for it.
+   */
+  createCloseCredentialInstruction(
+    credentialAccount: PublicKey,
+    robotAccount: PublicKey,
+    closer: PublicKey,
+    receiver: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.instruction(
+      [
+        { pubkey: credentialAccount, isSigner: false, isWritable: true },
+        { pubkey: receiver, isSigner: false, isWritable: true },
+        this.authorityKey(closer, authorization),
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: currentAuthority, isSigner: true, isWritable: false },
+        { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.from([RobyInstructionKind.CloseCredential])
+    );
   }
 
-  createAddOperatorInstruction(
+  /**
+   * Closes a finished command log. Once the robot is closed anyone may
+   * close its logs, refunding the executor as `receiver`.
+   */
+  createCloseCommandLogInstruction(
+    commandLogAccount: PublicKey,
     robotAccount: PublicKey,
     authority: PublicKey,
-    operator: PublicKey
+    receiver: PublicKey,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.concat([Buffer.from([6]), operator.toBuffer()]);
+    return this.instruction(
+      [
+        { pubkey: commandLogAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(authority, authorization),
+        { pubkey: receiver, isSigner: false, isWritable: true },
+        ...this.trailingKeys(authorization),
+      ],
+      Buffer.from([RobyInstructionKind.CloseCommandLog])
+    );
+  }
 
-    return new TransactionInstruction({
-      keys: [
-        { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+  /**
+   * Rewrites a pre-discriminator account in the current layout. A command
+   * log migrated by the robot's authority instead of its executor needs
+   * the already migrated robot.
+   */
+  createMigrateAccountInstruction(
+    targetAccount: PublicKey,
+    accountType: AccountType,
+    payer: PublicKey,
+    signer: PublicKey,
+    robotAccount?: PublicKey
+  ): TransactionInstruction {
+    const keys: AccountMeta[] = [
+      { pubkey: targetAccount, isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: signer, isSigner: true, isWritable: false },
+    ];
+    if (robotAccount) {
+      keys.push({ pubkey: robotAccount, isSigner: false, isWritable: false });
+    }
+
+    return this.instruction(
+      keys,
+      Buffer.from([RobyInstructionKind.MigrateAccount, accountType])
+    );
+  }
+
+  /** Credential accounts must be given in the order of their leaves. */
+  createVerifyCredentialsInstruction(
+    robotAccount: PublicKey,
+    credentialAccounts: PublicKey[],
+    multiproof: CredentialMultiProof,
+    revocationProof: SparseMerkleMultiProof
+  ): TransactionInstruction {
+    const encodedMultiproof =
+      multiproof.scheme === MerkleTreeScheme.SortedPair
+        ? Buffer.concat([
+            Buffer.from([MerkleTreeScheme.SortedPair]),
+            this.encodeHashes(multiproof.proof.proof),
+            this.encodeVecOf(multiproof.proof.proofFlags, (flag) => this.encodeBool(flag)),
+          ])
+        : Buffer.concat([
+            Buffer.from([MerkleTreeScheme.Positional, multiproof.proof.depth]),
+            this.encodeVecOf(multiproof.proof.indices, (index) => this.encodeU32(index)),
+            this.encodeHashes(multiproof.proof.proof),
+          ]);
+    const data = Buffer.concat([
+      Buffer.from([RobyInstructionKind.VerifyCredentials]),
+      encodedMultiproof,
+      this.encodeVecOf(revocationProof.siblingSlots, (slot) => this.encodeU16(slot)),
+      this.encodeHashes(revocationProof.siblings),
+    ]);
+
+    const keys: AccountMeta[] = [
+      { pubkey: robotAccount, isSigner: false, isWritable: false },
+      { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+    ];
+    if (multiproof.scheme === MerkleTreeScheme.Positional) {
+      const [treeAccount] = deriveCredentialTreeAddress(this.programId, robotAccount);
+      keys.push({ pubkey: treeAccount, isSigner: false, isWritable: false });
+    }
+
+    return this.instruction([...keys, ...this.readonlyKeys(credentialAccounts)], data);
+  }
+
+  createInitializeCredentialTreeInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    payer: PublicKey,
+    depth: number,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [treeAccount] = deriveCredentialTreeAddress(this.programId, robotAccount);
+
+    return this.instruction(
+      [
+        { pubkey: treeAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(authority, authorization),
+        { pubkey: payer, isSigner: true, isWritable: true },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.from([RobyInstructionKind.InitializeCredentialTree, depth])
+    );
   }
 
-  createRemoveOperatorInstruction(
+  createUpdateRevocationRootInstruction(
     robotAccount: PublicKey,
     authority: PublicKey,
-    operator: PublicKey
+    newRevocationRoot: Buffer,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.concat([Buffer.from([7]), operator.toBuffer()]);
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.UpdateRevocationRoot]),
+        newRevocationRoot,
+      ]),
+      authorization
+    );
+  }
 
-    return new TransactionInstruction({
-      keys: [
+  createSetRootGracePeriodInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    gracePeriod: number,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.SetRootGracePeriod]),
+        this.encodeI64(gracePeriod),
+      ]),
+      authorization
+    );
+  }
+
+  createInitializeCommandQueueInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    payer: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [queueAccount] = deriveCommandQueueAddress(this.programId, robotAccount);
+
+    return this.instruction(
+      [
+        { pubkey: queueAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(authority, authorization),
+        { pubkey: payer, isSigner: true, isWritable: true },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
+      ],
+      Buffer.from([RobyInstructionKind.InitializeCommandQueue])
+    );
+  }
+
+  /** Signed by the robot's device key. */
+  createAcknowledgeCommandInstruction(
+    robotAccount: PublicKey,
+    deviceKey: PublicKey,
+    commandLogAccount: PublicKey,
+    sequence: number,
+    status: CommandStatus,
+    errorCode: number = 0
+  ): TransactionInstruction {
+    const [queueAccount] = deriveCommandQueueAddress(this.programId, robotAccount);
+
+    return this.instruction(
+      [
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+        { pubkey: deviceKey, isSigner: true, isWritable: false },
+        { pubkey: queueAccount, isSigner: false, isWritable: true },
+        { pubkey: commandLogAccount, isSigner: false, isWritable: true },
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.AcknowledgeCommand]),
+        this.encodeU64(sequence),
+        Buffer.from([status]),
+        this.encodeU32(errorCode),
+      ])
+    );
   }
 
-  createEmergencyStopInstruction(
+  /** With `oldDeviceKey` the current device key co-signs the rotation. */
+  createRotateDeviceKeyInstruction(
     robotAccount: PublicKey,
-    authority: PublicKey
+    authority: PublicKey,
+    newDeviceKey: PublicKey,
+    oldDeviceKey?: PublicKey,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.from([8]);
+    const keys: AccountMeta[] = [
+      { pubkey: robotAccount, isSigner: false, isWritable: true },
+      this.authorityKey(authority, authorization),
+    ];
+    if (oldDeviceKey) {
+      keys.push({ pubkey: oldDeviceKey, isSigner: true, isWritable: false });
+    }
+
+    return this.instruction(
+      [...keys, ...this.trailingKeys(authorization)],
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.RotateDeviceKey]),
+        newDeviceKey.toBuffer(),
+        this.encodeBool(oldDeviceKey !== undefined),
+      ])
+    );
+  }
 
-    return new TransactionInstruction({
-      keys: [
+  /** Signed by the robot's device key. */
+  createReportStatusInstruction(
+    robotAccount: PublicKey,
+    deviceKey: PublicKey,
+    status: RobotStatus
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      deviceKey,
+      Buffer.from([RobyInstructionKind.ReportStatus, status])
+    );
+  }
+
+  /** Signed by the robot's device key. */
+  createHeartbeatInstruction(
+    robotAccount: PublicKey,
+    deviceKey: PublicKey,
+    batteryLevel?: number,
+    healthFlags?: number
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      deviceKey,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.Heartbeat]),
+        this.encodeOption(batteryLevel, (level) => Buffer.from([level])),
+        this.encodeOption(healthFlags, (flags) => this.encodeU32(flags)),
+      ])
+    );
+  }
+
+  /** Zero disables the liveness check. */
+  createSetLivenessTimeoutInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    livenessTimeout: number,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.SetLivenessTimeout]),
+        this.encodeI64(livenessTimeout),
+      ]),
+      authorization
+    );
+  }
+
+  createAddGuardianInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    guardian: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([Buffer.from([RobyInstructionKind.AddGuardian]), guardian.toBuffer()]),
+      authorization
+    );
+  }
+
+  createRemoveGuardianInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    guardian: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([Buffer.from([RobyInstructionKind.RemoveGuardian]), guardian.toBuffer()]),
+      authorization
+    );
+  }
+
+  createSetResumePolicyInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    resumeThreshold: number,
+    proposalTtl: number,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.SetResumePolicy, resumeThreshold]),
+        this.encodeI64(proposalTtl),
+      ]),
+      authorization
+    );
+  }
+
+  createProposeResumeInstruction(
+    robotAccount: PublicKey,
+    proposer: PublicKey,
+    payer: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [proposalAccount] = deriveResumeProposalAddress(this.programId, robotAccount);
+
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(proposer, authorization),
+        { pubkey: payer, isSigner: true, isWritable: true },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
+      ],
+      Buffer.from([RobyInstructionKind.ProposeResume])
+    );
+  }
+
+  createApproveResumeInstruction(
+    robotAccount: PublicKey,
+    approver: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [proposalAccount] = deriveResumeProposalAddress(this.programId, robotAccount);
+
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(approver, authorization),
+        ...this.trailingKeys(authorization),
+      ],
+      Buffer.from([RobyInstructionKind.ApproveResume])
+    );
+  }
+
+  createInitializeMultisigInstruction(
+    creator: PublicKey,
+    multisigId: Buffer,
+    signers: PublicKey[],
+    threshold: number
+  ): TransactionInstruction {
+    const [multisigAccount] = deriveMultisigAddress(this.programId, creator, multisigId);
+
+    return this.instruction(
+      [
+        { pubkey: multisigAccount, isSigner: false, isWritable: true },
+        { pubkey: creator, isSigner: true, isWritable: true },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      ],
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.InitializeMultisig]),
+        multisigId,
+        this.encodeVecOf(signers, (signer) => signer.toBuffer()),
+        Buffer.from([threshold]),
+      ])
+    );
+  }
+
+  createConfigureProposalsInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    approvers: PublicKey[],
+    threshold: number,
+    timelock: number,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.ConfigureProposals]),
+        this.encodeVecOf(approvers, (approver) => approver.toBuffer()),
+        Buffer.from([threshold]),
+        this.encodeI64(timelock),
+      ]),
+      authorization
+    );
+  }
+
+  /**
+   * Proposes `proposed`, an instruction built by this class that requires
+   * a proposal. `proposalId` is the robot's current `proposalCount`.
+   */
+  createCreateProposalInstruction(
+    robotAccount: PublicKey,
+    proposer: PublicKey,
+    payer: PublicKey,
+    proposalId: number,
+    proposed: TransactionInstruction,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [proposalAccount] = deriveProposalAddress(this.programId, robotAccount, proposalId);
+
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+        this.authorityKey(proposer, authorization),
+        { pubkey: payer, isSigner: true, isWritable: true },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.CreateProposal]),
+        this.encodeVec(proposed.data),
+      ])
+    );
   }
 
-  createResumeInstruction(
+  createApproveProposalInstruction(
     robotAccount: PublicKey,
-    authority: PublicKey
+    proposalId: number,
+    approver: PublicKey,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.from([9]);
+    const [proposalAccount] = deriveProposalAddress(this.programId, robotAccount, proposalId);
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
+        { pubkey: robotAccount, isSigner: false, isWritable: false },
+        this.authorityKey(approver, authorization),
+        ...this.trailingKeys(authorization),
+      ],
+      Buffer.from([RobyInstructionKind.ApproveProposal])
+    );
+  }
+
+  /** `payer` is the proposal's payer, who gets its rent back. */
+  createCancelProposalInstruction(
+    robotAccount: PublicKey,
+    proposalId: number,
+    canceller: PublicKey,
+    payer: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    const [proposalAccount] = deriveProposalAddress(this.programId, robotAccount, proposalId);
+
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+        this.authorityKey(canceller, authorization),
+        { pubkey: payer, isSigner: false, isWritable: true },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.from([RobyInstructionKind.CancelProposal])
+    );
   }
 
-  createUpdateRobotStatusInstruction(
+  /**
+   * Runs an approved proposal. `proposed` is the instruction that was
+   * proposed; the program signs for the role it checks, so none of its
+   * accounts need to sign here.
+   */
+  createExecuteProposalInstruction(
+    robotAccount: PublicKey,
+    proposalId: number,
+    payer: PublicKey,
+    proposed: TransactionInstruction
+  ): TransactionInstruction {
+    const [proposalAccount] = deriveProposalAddress(this.programId, robotAccount, proposalId);
+
+    return this.instruction(
+      [
+        { pubkey: proposalAccount, isSigner: false, isWritable: true },
+        { pubkey: payer, isSigner: false, isWritable: true },
+        ...proposed.keys.map((key) => ({ ...key, isSigner: false })),
+      ],
+      Buffer.from([RobyInstructionKind.ExecuteProposal])
+    );
+  }
+
+  createAcceptOwnershipInstruction(
+    robotAccount: PublicKey,
+    newOwner: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      newOwner,
+      Buffer.from([RobyInstructionKind.AcceptOwnership]),
+      authorization
+    );
+  }
+
+  createAcceptAuthorityInstruction(
+    robotAccount: PublicKey,
+    newAuthority: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      newAuthority,
+      Buffer.from([RobyInstructionKind.AcceptAuthority]),
+      authorization
+    );
+  }
+
+  /** The owner cancels a pending ownership transfer, the authority a pending authority transfer. */
+  createCancelTransferInstruction(
+    robotAccount: PublicKey,
+    signer: PublicKey,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      signer,
+      Buffer.from([RobyInstructionKind.CancelTransfer]),
+      authorization
+    );
+  }
+
+  createSetOperatorPolicyInstruction(
     robotAccount: PublicKey,
     authority: PublicKey,
-    status: number
+    policy: OperatorPolicy,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.from([10, status]);
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.from([RobyInstructionKind.SetOperatorPolicy, policy]),
+      authorization
+    );
+  }
 
-    return new TransactionInstruction({
-      keys: [
-        { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: authority, isSigner: true, isWritable: false },
+  /** `minLevel` must be at least `Operator`. */
+  createSetCommandPermissionInstruction(
+    robotAccount: PublicKey,
+    authority: PublicKey,
+    commandType: CommandType,
+    minLevel: PermissionLevel,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.robotInstruction(
+      robotAccount,
+      authority,
+      Buffer.from([RobyInstructionKind.SetCommandPermission, commandType, minLevel]),
+      authorization
+    );
+  }
+
+  /** The delegator signs and pays for the sub-credential. */
+  createDelegateCredentialInstruction(
+    params: DelegateCredentialParams
+  ): TransactionInstruction {
+    const [credentialAccount] = deriveCredentialAddress(
+      this.programId,
+      params.robot,
+      params.recipient,
+      params.credentialHash
+    );
+
+    return this.instruction(
+      [
+        { pubkey: credentialAccount, isSigner: false, isWritable: true },
+        { pubkey: params.robot, isSigner: false, isWritable: true },
+        { pubkey: params.delegator, isSigner: true, isWritable: true },
+        { pubkey: params.recipient, isSigner: false, isWritable: false },
+        { pubkey: params.parentCredential, isSigner: false, isWritable: false },
+        { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.DelegateCredential, params.permissionLevel]),
+        this.encodeI64(params.validFrom),
+        this.encodeI64(params.validUntil),
+        params.credentialHash,
+        this.encodeScope(params.scope),
+      ])
+    );
   }
 
-  createTransferOwnershipInstruction(
+  /** Lets the authority fail a command the robot never finished. */
+  createFailCommandInstruction(
     robotAccount: PublicKey,
-    currentOwner: PublicKey,
-    newOwner: PublicKey
+    authority: PublicKey,
+    commandLogAccount: PublicKey,
+    sequence: number,
+    errorCode: number,
+    authorization: Authorization = {}
   ): TransactionInstruction {
-    const data = Buffer.concat([Buffer.from([11]), newOwner.toBuffer()]);
+    const [queueAccount] = deriveCommandQueueAddress(this.programId, robotAccount);
 
-    return new TransactionInstruction({
-      keys: [
+    return this.instruction(
+      [
         { pubkey: robotAccount, isSigner: false, isWritable: true },
-        { pubkey: currentOwner, isSigner: true, isWritable: false },
+        this.authorityKey(authority, authorization),
+        { pubkey: queueAccount, isSigner: false, isWritable: true },
+        { pubkey: commandLogAccount, isSigner: false, isWritable: true },
+        ...this.trailingKeys(authorization),
       ],
-      programId: this.programId,
-      data,
-    });
+      Buffer.concat([
+        Buffer.from([RobyInstructionKind.FailCommand]),
+        this.encodeU64(sequence),
+        this.encodeU32(errorCode),
+      ])
+    );
   }
 
-  private encodeString(str: string): Uint8Array {
-    const encoded = Buffer.from(str, 'utf8');
-    const length = Buffer.alloc(4);
-    length.writeUInt32LE(encoded.length, 0);
-    return Buffer.concat([length, encoded]);
+  private instruction(keys: AccountMeta[], data: Buffer): TransactionInstruction {
+    return new TransactionInstruction({ keys, programId: this.programId, data });
   }
 
-  private encodeI64(value: number): Buffer {
-    const buffer = Buffer.alloc(8);
-    buffer.writeBigInt64LE(BigInt(value), 0);
-    return buffer;
+  /** Instructions taking the robot and one signing role. */
+  private robotInstruction(
+    robotAccount: PublicKey,
+    signer: PublicKey,
+    data: Buffer,
+    authorization: Authorization = {}
+  ): TransactionInstruction {
+    return this.instruction(
+      [
+        { pubkey: robotAccount, isSigner: false, isWritable: true },
+        this.authorityKey(signer, authorization),
+        ...this.trailingKeys(authorization),
+      ],
+      data
+    );
   }
 
-  private encodeVec(data: Buffer): Buffer {
-    const length = Buffer.alloc(4);
-    length.writeUInt32LE(data.length, 0);
-    return Buffer.concat([length, data]);
+  /** A multisig authority does not sign itself; its members do. */
+  private authorityKey(authority: PublicKey, authorization: Authorization): AccountMeta {
+    const isMultisig = (authorization.multisigSigners ?? []).length > 0;
+    return { pubkey: authority, isSigner: !isMultisig, isWritable: false };
   }
-}
 
+  private trailingKeys(authorization: Authorization): AccountMeta[] {
+    if (authorization.adminProof) {
+      return this.readonlyKeys(authorization.adminCredentials ?? []);
+    }
+    return (authorization.multisigSigners ?? []).map((pubkey) => ({
+      pubkey,
+      isSigner: true,
+      isWritable: false,
+    }));
+  }
+
+  private readonlyKeys(pubkeys: PublicKey[]): AccountMeta[] {
+    return pubkeys.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+  }
 
+  private encodeScope(scope: CredentialScope): Buffer {
+    return Buffer.concat([
+      this.encodeU16(scope.allowedCommands),
+      this.encodeBool(scope.canDelegate),
+      Buffer.from([scope.maxDelegationDepth]),
+      this.encodeVecOf(scope.parameterBounds, (bound) =>
+        Buffer.concat([
+          Buffer.from([bound.commandType]),
+          this.encodeU16(bound.offset),
+          Buffer.from([bound.kind]),
+          this.encodeI64(bound.min),
+          this.encodeI64(bound.max),
+        ])
+      ),
+      this.encodeVecOf(scope.timeWindows, (window) =>
+        Buffer.concat([this.encodeU16(window.startMinute), this.encodeU16(window.endMinute)])
+      ),
+    ]);
+  }
+
+  private encodeProof(proof: CredentialProof): Buffer {
+    return Buffer.concat([
+      this.encodeHashes(proof.merkleProof),
+      this.encodeU32(proof.leafIndex),
+      this.encodeSparseProof(proof.revocationProof),
+    ]);
+  }
+
+  private encodeSparseProof(proof: SparseMerkleProof): Buffer {
+    return Buffer.concat([proof.siblingBitmap, this.encodeHashes(proof.siblings)]);
+  }
+
+  private encodeHashes(hashes: Buffer[]): Buffer {
+    return this.encodeVecOf(hashes, (hash) => hash);
+  }
 
+  private encodeString(str: string): Buffer {
+    return this.encodeVec(Buffer.from(str, 'utf8'));
+  }
 
+  private encodeBool(value: boolean): Buffer {
+    return Buffer.from([value ? 1 : 0]);
+  }
 
+  private encodeU16(value: number): Buffer {
+    const buffer = Buffer.alloc(2);
+    buffer.writeUInt16LE(value, 0);
+    return buffer;
+  }
 
+  private encodeU32(value: number): Buffer {
+    const buffer = Buffer.alloc(4);
+    buffer.writeUInt32LE(value, 0);
+    return buffer;
+  }
 
+  private encodeU64(value: number): Buffer {
+    const buffer = Buffer.alloc(8);
+    buffer.writeBigUInt64LE(BigInt(value), 0);
+    return buffer;
+  }
 
+  private encodeI64(value: number): Buffer {
+    const buffer = Buffer.alloc(8);
+    buffer.writeBigInt64LE(BigInt(value), 0);
+    return buffer;
+  }
 
+  private encodeOption<T>(value: T | undefined, encode: (value: T) => Buffer): Buffer {
+    return value === undefined
+      ? Buffer.from([0])
+      : Buffer.concat([Buffer.from([1]), encode(value)]);
+  }
 
+  private encodeVec(data: Buffer): Buffer {
+    return Buffer.concat([this.encodeU32(data.length), data]);
+  }
 
+  private encodeVecOf<T>(items: T[], encode: (item: T) => Buffer): Buffer {
+    return Buffer.concat([this.encodeU32(items.length), ...items.map(encode)]);
+  }
+}
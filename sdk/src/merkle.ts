@@ -1,40 +1,68 @@
-import { createHash } from 'crypto';
+import { PublicKey } from '@solana/web3.js';
+import { keccak_256 } from '@noble/hashes/sha3';
+import { MerkleTreeScheme } from './types';
+
+/** Leaves and internal nodes are hashed in separate domains, as on-chain. */
+export const LEAF_PREFIX = 0x00;
+export const NODE_PREFIX = 0x01;
+/** Padding leaf used to fill trees up to a power of two. */
+export const EMPTY_LEAF = Buffer.alloc(32);
+
+function keccak(...parts: Buffer[]): Buffer {
+  return Buffer.from(keccak_256(Buffer.concat(parts)));
+}
+
+export function hashLeaf(data: Buffer): Buffer {
+  return keccak(Buffer.from([LEAF_PREFIX]), data);
+}
 
+export function hashPair(a: Buffer, b: Buffer): Buffer {
+  return keccak(Buffer.from([NODE_PREFIX]), a, b);
+}
+
+export function hashSortedPair(a: Buffer, b: Buffer): Buffer {
+  return Buffer.compare(a, b) <= 0 ? hashPair(a, b) : hashPair(b, a);
+}
+
+function combine(scheme: MerkleTreeScheme, left: Buffer, right: Buffer): Buffer {
+  return scheme === MerkleTreeScheme.SortedPair
+    ? hashSortedPair(left, right)
+    : hashPair(left, right);
+}
+
+/**
+ * Credential tree matching the program's `MerkleTree`: the leaves are
+ * credential leaves (see `createCredentialLeaf`), padded with `EMPTY_LEAF`
+ * to a power of two.
+ */
 export class MerkleTree {
-  private leaves: Buffer[];
   private layers: Buffer[][];
 
-  constructor(leaves: Buffer[]) {
-    this.leaves = leaves.map((leaf) => this.hashLeaf(leaf));
-    this.layers = this.buildTree();
-  }
-
-  private hashLeaf(data: Buffer): Buffer {
-    return createHash('sha256').update(data).digest();
+  constructor(
+    leaves: Buffer[],
+    private scheme: MerkleTreeScheme = MerkleTreeScheme.SortedPair
+  ) {
+    this.layers = this.buildTree(leaves);
   }
 
-  private hashPair(a: Buffer, b: Buffer): Buffer {
-    const combined = Buffer.concat([a, b].sort(Buffer.compare));
-    return createHash('sha256').update(combined).digest();
-  }
+  private buildTree(leaves: Buffer[]): Buffer[][] {
+    let size = 1;
+    while (size < leaves.length) {
+      size *= 2;
+    }
 
-  private buildTree(): Buffer[][] {
-    if (this.leaves.length === 0) {
-      return [[]];
+    let currentLayer = [...leaves];
+    while (currentLayer.length < size) {
+      currentLayer.push(EMPTY_LEAF);
     }
 
-    const layers: Buffer[][] = [this.leaves];
-    let currentLayer = this.leaves;
+    const layers: Buffer[][] = [currentLayer];
 
     while (currentLayer.length > 1) {
       const nextLayer: Buffer[] = [];
 
       for (let i = 0; i < currentLayer.length; i += 2) {
-        if (i + 1 < currentLayer.length) {
-          nextLayer.push(this.hashPair(currentLayer[i], currentLayer[i + 1]));
-        } else {
-          nextLayer.push(currentLayer[i]);
-        }
+        nextLayer.push(combine(this.scheme, currentLayer[i], currentLayer[i + 1]));
       }
 
       layers.push(nextLayer);
@@ -45,52 +73,72 @@ export class MerkleTree {
   }
 
   getRoot(): Buffer {
-    if (this.layers.length === 0 || this.layers[this.layers.length - 1].length === 0) {
-      return Buffer.alloc(32);
-    }
     return this.layers[this.layers.length - 1][0];
   }
 
-  getProof(leaf: Buffer): Buffer[] {
-    const hashedLeaf = this.hashLeaf(leaf);
-    let index = this.leaves.findIndex((l) => l.equals(hashedLeaf));
+  getLeafIndex(leaf: Buffer): number {
+    const index = this.layers[0].findIndex((l) => l.equals(leaf));
 
     if (index === -1) {
       throw new Error('Leaf not found in tree');
     }
 
-    const proof: Buffer[] = [];
+    return index;
+  }
 
-    for (let i = 0; i < this.layers.length - 1; i++) {
-      const layer = this.layers[i];
-      const isRightNode = index % 2 === 1;
-      const siblingIndex = isRightNode ? index - 1 : index + 1;
+  getProof(leaf: Buffer): Buffer[] {
+    return this.getProofAt(this.getLeafIndex(leaf));
+  }
 
-      if (siblingIndex < layer.length) {
-        proof.push(layer[siblingIndex]);
-      }
+  getProofAt(index: number): Buffer[] {
+    if (index < 0 || index >= this.layers[0].length) {
+      throw new Error('Leaf index out of range');
+    }
+
+    const proof: Buffer[] = [];
 
+    for (let i = 0; i < this.layers.length - 1; i++) {
+      proof.push(this.layers[i][index ^ 1]);
       index = Math.floor(index / 2);
     }
 
     return proof;
   }
 
-  static verify(leaf: Buffer, proof: Buffer[], root: Buffer): boolean {
-    let computedHash = createHash('sha256').update(leaf).digest();
+  /** `index` is only read for positional trees. */
+  static verify(
+    leaf: Buffer,
+    proof: Buffer[],
+    root: Buffer,
+    scheme: MerkleTreeScheme = MerkleTreeScheme.SortedPair,
+    index: number = 0
+  ): boolean {
+    if (
+      scheme === MerkleTreeScheme.Positional &&
+      Math.floor(index / Math.pow(2, proof.length)) !== 0
+    ) {
+      return false;
+    }
+
+    let computedHash = leaf;
 
     for (const proofElement of proof) {
-      const combined = Buffer.concat(
-        [computedHash, proofElement].sort(Buffer.compare)
-      );
-      computedHash = createHash('sha256').update(combined).digest();
+      if (scheme === MerkleTreeScheme.SortedPair) {
+        computedHash = hashSortedPair(computedHash, proofElement);
+      } else {
+        computedHash =
+          index % 2 === 0
+            ? hashPair(computedHash, proofElement)
+            : hashPair(proofElement, computedHash);
+        index = Math.floor(index / 2);
+      }
     }
 
     return computedHash.equals(root);
   }
 
   getLeaves(): Buffer[] {
-    return [...this.leaves];
+    return [...this.layers[0]];
   }
 
   getLayerCount(): number {
@@ -98,32 +146,30 @@ export class MerkleTree {
   }
 }
 
+/**
+ * Leaf committed to the credential tree for one credential. Binds the
+ * holder, the robot, the level and the validity window, as the program's
+ * `credential_leaf` does.
+ */
 export function createCredentialLeaf(
-  owner: string,
-  robot: string,
+  owner: PublicKey,
+  robot: PublicKey,
   permissionLevel: number,
   validFrom: number,
-  validUntil: number
+  validUntil: number,
+  credentialHash: Buffer
 ): Buffer {
-  const data = Buffer.concat([
-    Buffer.from(owner),
-    Buffer.from(robot),
-    Buffer.from([permissionLevel]),
-    Buffer.from(validFrom.toString()),
-    Buffer.from(validUntil.toString()),
-  ]);
-  return createHash('sha256').update(data).digest();
+  const window = Buffer.alloc(16);
+  window.writeBigInt64LE(BigInt(validFrom), 0);
+  window.writeBigInt64LE(BigInt(validUntil), 8);
+
+  return hashLeaf(
+    Buffer.concat([
+      owner.toBuffer(),
+      robot.toBuffer(),
+      Buffer.from([permissionLevel]),
+      window,
+      credentialHash,
+    ])
+  );
 }
-
-
-
-
-
-
-
-
-
-
-
-
-
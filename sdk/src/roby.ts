@@ -2,24 +2,49 @@ import {
   Connection,
   PublicKey,
   Transaction,
+  TransactionInstruction,
   Keypair,
   SystemProgram,
   sendAndConfirmTransaction,
   TransactionSignature,
-  AccountInfo,
+  GetProgramAccountsFilter,
 } from '@solana/web3.js';
+import bs58 from 'bs58';
 import { RobyInstructions } from './instructions';
-import { RobotAccount, CredentialAccount, CommandLogAccount } from './state';
+import {
+  RobotAccount,
+  CredentialAccount,
+  CommandLogAccount,
+  CredentialTreeAccount,
+  CommandQueueAccount,
+  ResumeProposalAccount,
+  MultisigAccount,
+  ProposalAccount,
+} from './state';
 import {
   RobotData,
   CredentialData,
+  CommandLogData,
+  CredentialTreeData,
+  CommandQueueData,
+  ResumeProposalData,
+  MultisigData,
+  ProposalData,
   InitializeRobotParams,
   IssueCredentialParams,
+  DelegateCredentialParams,
   ExecuteCommandParams,
-  CommandType,
-  PermissionLevel,
+  Authorization,
+  CommandStatus,
 } from './types';
-import { MerkleTree, createCredentialLeaf } from './merkle';
+import {
+  deriveRobotAddress,
+  deriveCredentialAddress,
+  deriveCredentialTreeAddress,
+  deriveCommandQueueAddress,
+  deriveResumeProposalAddress,
+  deriveProposalAddress,
+} from './utils';
 
 export class RobyClient {
   private instructions: RobyInstructions;
@@ -31,102 +56,181 @@ export class RobyClient {
     this.instructions = new RobyInstructions(programId);
   }
 
+  /**
+   * Creates the robot at its PDA. When `authority` signs as well, the
+   * robot's command queue is created in the same transaction; otherwise
+   * the authority must call `initializeCommandQueue` before any command
+   * can run.
+   */
   async initializeRobot(
     params: InitializeRobotParams,
-    payer: Keypair
+    owner: Keypair,
+    authority?: Keypair
   ): Promise<{ signature: TransactionSignature; robotAccount: PublicKey }> {
-    const robotAccount = Keypair.generate();
-    const lamports = await this.connection.getMinimumBalanceForRentExemption(
-      1024
+    const [robotAccount] = deriveRobotAddress(
+      this.programId,
+      params.owner,
+      params.robotId
     );
 
     const transaction = new Transaction().add(
-      SystemProgram.createAccount({
-        fromPubkey: payer.publicKey,
-        newAccountPubkey: robotAccount.publicKey,
-        lamports,
-        space: 1024,
-        programId: this.programId,
-      }),
-      this.instructions.createInitializeRobotInstruction(
-        params,
-        robotAccount.publicKey
-      )
+      this.instructions.createInitializeRobotInstruction(params)
+    );
+    const signers = [owner];
+    if (authority && authority.publicKey.equals(params.authority)) {
+      transaction.add(
+        this.instructions.createInitializeCommandQueueInstruction(
+          robotAccount,
+          authority.publicKey,
+          owner.publicKey
+        )
+      );
+      signers.push(authority);
+    }
+
+    const signature = await this.send(transaction, signers);
+
+    return {
+      signature,
+      robotAccount,
+    };
+  }
+
+  async initializeCommandQueue(
+    robotAccount: PublicKey,
+    authority: Keypair,
+    payer: Keypair = authority
+  ): Promise<{ signature: TransactionSignature; commandQueue: PublicKey }> {
+    const [commandQueue] = deriveCommandQueueAddress(this.programId, robotAccount);
+    const signature = await this.send(
+      new Transaction().add(
+        this.instructions.createInitializeCommandQueueInstruction(
+          robotAccount,
+          authority.publicKey,
+          payer.publicKey
+        )
+      ),
+      [authority, payer]
     );
 
-    const signature = await sendAndConfirmTransaction(
-      this.connection,
-      transaction,
-      [payer, robotAccount]
+    return {
+      signature,
+      commandQueue,
+    };
+  }
+
+  async initializeCredentialTree(
+    robotAccount: PublicKey,
+    authority: Keypair,
+    depth: number,
+    payer: Keypair = authority
+  ): Promise<{ signature: TransactionSignature; credentialTree: PublicKey }> {
+    const [credentialTree] = deriveCredentialTreeAddress(this.programId, robotAccount);
+    const signature = await this.send(
+      new Transaction().add(
+        this.instructions.createInitializeCredentialTreeInstruction(
+          robotAccount,
+          authority.publicKey,
+          payer.publicKey,
+          depth
+        )
+      ),
+      [authority, payer]
     );
 
     return {
       signature,
-      robotAccount: robotAccount.publicKey,
+      credentialTree,
     };
   }
 
+  /**
+   * Issues a credential at its PDA. `payer` pays the rent and gets it back
+   * when the credential is closed; the issuer signs as well unless it is
+   * the payer.
+   */
   async issueCredential(
     params: IssueCredentialParams,
-    payer: Keypair
+    payer: Keypair,
+    issuer: Keypair = payer,
+    authorization: Authorization = {}
   ): Promise<{ signature: TransactionSignature; credentialAccount: PublicKey }> {
-    const credentialAccount = Keypair.generate();
-    const lamports = await this.connection.getMinimumBalanceForRentExemption(
-      512
+    const [credentialAccount] = deriveCredentialAddress(
+      this.programId,
+      params.robot,
+      params.recipient,
+      params.credentialHash
     );
 
-    const transaction = new Transaction().add(
-      SystemProgram.createAccount({
-        fromPubkey: payer.publicKey,
-        newAccountPubkey: credentialAccount.publicKey,
-        lamports,
-        space: 512,
-        programId: this.programId,
-      }),
-      this.instructions.createIssueCredentialInstruction(
-        params,
-        credentialAccount.publicKey,
-        params.robot
-      )
+    const signature = await this.send(
+      new Transaction().add(
+        this.instructions.createIssueCredentialInstruction(
+          { ...params, payer: payer.publicKey },
+          authorization
+        )
+      ),
+      [payer, issuer]
     );
 
-    const signature = await sendAndConfirmTransaction(
-      this.connection,
-      transaction,
-      [payer, credentialAccount]
+    return {
+      signature,
+      credentialAccount,
+    };
+  }
+
+  async delegateCredential(
+    params: DelegateCredentialParams,
+    delegator: Keypair
+  ): Promise<{ signature: TransactionSignature; credentialAccount: PublicKey }> {
+    const [credentialAccount] = deriveCredentialAddress(
+      this.programId,
+      params.robot,
+      params.recipient,
+      params.credentialHash
+    );
+
+    const signature = await this.send(
+      new Transaction().add(
+        this.instructions.createDelegateCredentialInstruction(params)
+      ),
+      [delegator]
     );
 
     return {
       signature,
-      credentialAccount: credentialAccount.publicKey,
+      credentialAccount,
     };
   }
 
   async revokeCredential(
     credentialAccount: PublicKey,
     robotAccount: PublicKey,
-    authority: Keypair
+    authority: Keypair,
+    authorization: Authorization = {}
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createRevokeCredentialInstruction(
         credentialAccount,
         robotAccount,
-        authority.publicKey
-      )
+        authority.publicKey,
+        authorization
+      ),
+      [authority]
     );
-
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
   }
 
+  /**
+   * Creates the command log and executes the command. The executor pays
+   * for the log and can reclaim it with `closeCommandLog` once the robot
+   * has finished the command.
+   */
   async executeCommand(
     params: ExecuteCommandParams,
     executor: Keypair
   ): Promise<{ signature: TransactionSignature; commandLogAccount: PublicKey }> {
     const commandLogAccount = Keypair.generate();
     const lamports = await this.connection.getMinimumBalanceForRentExemption(
-      512
+      CommandLogAccount.LEN
     );
 
     const transaction = new Transaction().add(
@@ -134,7 +238,7 @@ export class RobyClient {
         fromPubkey: executor.publicKey,
         newAccountPubkey: commandLogAccount.publicKey,
         lamports,
-        space: 512,
+        space: CommandLogAccount.LEN,
         programId: this.programId,
       }),
       this.instructions.createExecuteCommandInstruction(
@@ -143,11 +247,7 @@ export class RobyClient {
       )
     );
 
-    const signature = await sendAndConfirmTransaction(
-      this.connection,
-      transaction,
-      [executor, commandLogAccount]
-    );
+    const signature = await this.send(transaction, [executor, commandLogAccount]);
 
     return {
       signature,
@@ -155,40 +255,134 @@ export class RobyClient {
     };
   }
 
+  async acknowledgeCommand(
+    robotAccount: PublicKey,
+    deviceKey: Keypair,
+    commandLogAccount: PublicKey,
+    sequence: number,
+    status: CommandStatus,
+    errorCode: number = 0
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createAcknowledgeCommandInstruction(
+        robotAccount,
+        deviceKey.publicKey,
+        commandLogAccount,
+        sequence,
+        status,
+        errorCode
+      ),
+      [deviceKey]
+    );
+  }
+
+  async failCommand(
+    robotAccount: PublicKey,
+    authority: Keypair,
+    commandLogAccount: PublicKey,
+    sequence: number,
+    errorCode: number
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createFailCommandInstruction(
+        robotAccount,
+        authority.publicKey,
+        commandLogAccount,
+        sequence,
+        errorCode
+      ),
+      [authority]
+    );
+  }
+
+  async heartbeat(
+    robotAccount: PublicKey,
+    deviceKey: Keypair,
+    batteryLevel?: number,
+    healthFlags?: number
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createHeartbeatInstruction(
+        robotAccount,
+        deviceKey.publicKey,
+        batteryLevel,
+        healthFlags
+      ),
+      [deviceKey]
+    );
+  }
+
   async updateMerkleRoot(
     robotAccount: PublicKey,
     authority: Keypair,
     newMerkleRoot: Buffer
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createUpdateMerkleRootInstruction(
         robotAccount,
         authority.publicKey,
         newMerkleRoot
-      )
+      ),
+      [authority]
     );
-
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
   }
 
+  /** Starts a transfer; `newAuthority` completes it with `acceptAuthority`. */
   async transferAuthority(
     robotAccount: PublicKey,
     currentAuthority: Keypair,
     newAuthority: PublicKey
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createTransferAuthorityInstruction(
         robotAccount,
         currentAuthority.publicKey,
         newAuthority
-      )
+      ),
+      [currentAuthority]
     );
+  }
 
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      currentAuthority,
-    ]);
+  async acceptAuthority(
+    robotAccount: PublicKey,
+    newAuthority: Keypair
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createAcceptAuthorityInstruction(
+        robotAccount,
+        newAuthority.publicKey
+      ),
+      [newAuthority]
+    );
+  }
+
+  /** Starts a transfer; `newOwner` completes it with `acceptOwnership`. */
+  async transferOwnership(
+    robotAccount: PublicKey,
+    currentOwner: Keypair,
+    newOwner: PublicKey
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createTransferOwnershipInstruction(
+        robotAccount,
+        currentOwner.publicKey,
+        newOwner
+      ),
+      [currentOwner]
+    );
+  }
+
+  async acceptOwnership(
+    robotAccount: PublicKey,
+    newOwner: Keypair
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createAcceptOwnershipInstruction(
+        robotAccount,
+        newOwner.publicKey
+      ),
+      [newOwner]
+    );
   }
 
   async addOperator(
@@ -196,17 +390,14 @@ export class RobyClient {
     authority: Keypair,
     operator: PublicKey
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createAddOperatorInstruction(
         robotAccount,
         authority.publicKey,
         operator
-      )
+      ),
+      [authority]
     );
-
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
   }
 
   async removeOperator(
@@ -214,49 +405,106 @@ export class RobyClient {
     authority: Keypair,
     operator: PublicKey
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createRemoveOperatorInstruction(
         robotAccount,
         authority.publicKey,
         operator
-      )
+      ),
+      [authority]
     );
-
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
   }
 
   async emergencyStop(
     robotAccount: PublicKey,
-    authority: Keypair
+    stopper: Keypair,
+    reasonCode: number = 0
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    return this.sendInstruction(
       this.instructions.createEmergencyStopInstruction(
         robotAccount,
-        authority.publicKey
-      )
+        stopper.publicKey,
+        reasonCode
+      ),
+      [stopper]
     );
-
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
   }
 
+  /**
+   * Clears an emergency stop. If the robot has a resume policy, the
+   * approved resume proposal is consumed and its rent refunded.
+   */
   async resume(
     robotAccount: PublicKey,
     authority: Keypair
   ): Promise<TransactionSignature> {
-    const transaction = new Transaction().add(
+    const robot = await this.getRobotData(robotAccount);
+    let resumeProposalPayer: PublicKey | undefined;
+    if (robot && robot.emergencyStop && robot.resumeThreshold > 0) {
+      const proposal = await this.getResumeProposalData(robotAccount);
+      resumeProposalPayer = proposal?.payer;
+    }
+
+    return this.sendInstruction(
       this.instructions.createResumeInstruction(
         robotAccount,
-        authority.publicKey
-      )
+        authority.publicKey,
+        resumeProposalPayer
+      ),
+      [authority]
     );
+  }
 
-    return await sendAndConfirmTransaction(this.connection, transaction, [
-      authority,
-    ]);
+  async closeCredential(
+    credentialAccount: PublicKey,
+    robotAccount: PublicKey,
+    closer: Keypair,
+    receiver: PublicKey
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createCloseCredentialInstruction(
+        credentialAccount,
+        robotAccount,
+        closer.publicKey,
+        receiver
+      ),
+      [closer]
+    );
+  }
+
+  async closeCommandLog(
+    commandLogAccount: PublicKey,
+    robotAccount: PublicKey,
+    authority: Keypair,
+    receiver: PublicKey
+  ): Promise<TransactionSignature> {
+    return this.sendInstruction(
+      this.instructions.createCloseCommandLogInstruction(
+        commandLogAccount,
+        robotAccount,
+        authority.publicKey,
+        receiver
+      ),
+      [authority]
+    );
+  }
+
+  async closeRobot(
+    robotAccount: PublicKey,
+    owner: Keypair,
+    receiver: PublicKey
+  ): Promise<TransactionSignature> {
+    const proposal = await this.getResumeProposalData(robotAccount);
+
+    return this.sendInstruction(
+      this.instructions.createCloseRobotInstruction(
+        robotAccount,
+        owner.publicKey,
+        receiver,
+        proposal?.payer
+      ),
+      [owner]
+    );
   }
 
   async getRobotData(robotAccount: PublicKey): Promise<RobotData | null> {
@@ -279,21 +527,96 @@ export class RobyClient {
     return CredentialAccount.decode(accountInfo.data);
   }
 
+  async getCommandLogData(
+    commandLogAccount: PublicKey
+  ): Promise<CommandLogData | null> {
+    const accountInfo = await this.connection.getAccountInfo(commandLogAccount);
+    if (!accountInfo) {
+      return null;
+    }
+    return CommandLogAccount.decode(accountInfo.data);
+  }
+
+  async getCredentialTreeData(
+    robotAccount: PublicKey
+  ): Promise<CredentialTreeData | null> {
+    const [address] = deriveCredentialTreeAddress(this.programId, robotAccount);
+    const accountInfo = await this.connection.getAccountInfo(address);
+    if (!accountInfo) {
+      return null;
+    }
+    return CredentialTreeAccount.decode(accountInfo.data);
+  }
+
+  async getCommandQueueData(
+    robotAccount: PublicKey
+  ): Promise<CommandQueueData | null> {
+    const [address] = deriveCommandQueueAddress(this.programId, robotAccount);
+    const accountInfo = await this.connection.getAccountInfo(address);
+    if (!accountInfo) {
+      return null;
+    }
+    return CommandQueueAccount.decode(accountInfo.data);
+  }
+
+  async getResumeProposalData(
+    robotAccount: PublicKey
+  ): Promise<ResumeProposalData | null> {
+    const [address] = deriveResumeProposalAddress(this.programId, robotAccount);
+    const accountInfo = await this.connection.getAccountInfo(address);
+    if (!accountInfo) {
+      return null;
+    }
+    return ResumeProposalAccount.decode(accountInfo.data);
+  }
+
+  async getMultisigData(multisigAccount: PublicKey): Promise<MultisigData | null> {
+    const accountInfo = await this.connection.getAccountInfo(multisigAccount);
+    if (!accountInfo) {
+      return null;
+    }
+    return MultisigAccount.decode(accountInfo.data);
+  }
+
+  async getProposalData(
+    robotAccount: PublicKey,
+    proposalId: number
+  ): Promise<ProposalData | null> {
+    const [address] = deriveProposalAddress(this.programId, robotAccount, proposalId);
+    const accountInfo = await this.connection.getAccountInfo(address);
+    if (!accountInfo) {
+      return null;
+    }
+    return ProposalAccount.decode(accountInfo.data);
+  }
+
+  /** The owner follows the 8-byte discriminator and the version byte. */
   async getRobotsByOwner(owner: PublicKey): Promise<PublicKey[]> {
-    const accounts = await this.connection.getProgramAccounts(this.programId, {
-      filters: [
-        {
-          memcmp: {
-            offset: 1,
-            bytes: owner.toBase58(),
-          },
+    const filters: GetProgramAccountsFilter[] = [
+      {
+        memcmp: {
+          offset: 0,
+          bytes: bs58.encode(RobotAccount.DISCRIMINATOR),
         },
-      ],
+      },
+      {
+        memcmp: {
+          offset: RobotAccount.DISCRIMINATOR.length + 1,
+          bytes: owner.toBase58(),
+        },
+      },
+    ];
+    const accounts = await this.connection.getProgramAccounts(this.programId, {
+      filters,
     });
 
     return accounts.map((account) => account.pubkey);
   }
 
+  getInstructions(): RobyInstructions {
+    return this.instructions;
+  }
+
   getProgramId(): PublicKey {
     return this.programId;
   }
@@ -301,8 +624,25 @@ export class RobyClient {
   getConnection(): Connection {
     return this.connection;
   }
-}
 
+  private async sendInstruction(
+    instruction: TransactionInstruction,
+    signers: Keypair[]
+  ): Promise<TransactionSignature> {
+    return this.send(new Transaction().add(instruction), signers);
+  }
+
+  private async send(
+    transaction: Transaction,
+    signers: Keypair[]
+  ): Promise<TransactionSignature> {
+    const unique = signers.filter(
+      (signer, index) =>
+        signers.findIndex((other) => other.publicKey.equals(signer.publicKey)) === index
+    );
+    return await sendAndConfirmTransaction(this.connection, transaction, unique);
+  }
+}
 
 
 
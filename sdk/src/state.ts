@@ -1,205 +1,348 @@
 import { PublicKey } from '@solana/web3.js';
-import { deserialize, serialize } from 'borsh';
-import { RobotData, CredentialData, CommandLogData, RobotStatus, PermissionLevel, CommandType } from './types';
+import {
+  RobotData,
+  CredentialData,
+  CommandLogData,
+  CommandQueueData,
+  CredentialTreeData,
+  QueuedCommand,
+  ResumeProposalData,
+  MultisigData,
+  ProposalData,
+  CredentialScope,
+  RetiredRoot,
+  RobotStatus,
+  PermissionLevel,
+  CommandType,
+  CommandStatus,
+  MerkleTreeScheme,
+  OperatorPolicy,
+  ParameterKind,
+  COMMAND_TYPE_COUNT,
+} from './types';
+
+/**
+ * Every program account starts with an 8-byte discriminator and a one-byte
+ * layout version, followed by the Borsh-encoded fields.
+ */
+export const DISCRIMINATOR_LEN = 8;
+
+class AccountReader {
+  private offset: number;
+
+  constructor(private data: Buffer, offset: number) {
+    this.offset = offset;
+  }
+
+  u8(): number {
+    const value = this.data.readUInt8(this.offset);
+    this.offset += 1;
+    return value;
+  }
+
+  bool(): boolean {
+    return this.u8() !== 0;
+  }
+
+  u16(): number {
+    const value = this.data.readUInt16LE(this.offset);
+    this.offset += 2;
+    return value;
+  }
+
+  u32(): number {
+    const value = this.data.readUInt32LE(this.offset);
+    this.offset += 4;
+    return value;
+  }
+
+  u64(): number {
+    const value = this.data.readBigUInt64LE(this.offset);
+    this.offset += 8;
+    return Number(value);
+  }
+
+  i64(): number {
+    const value = this.data.readBigInt64LE(this.offset);
+    this.offset += 8;
+    return Number(value);
+  }
+
+  bytes(length: number): Buffer {
+    const value = Buffer.from(this.data.subarray(this.offset, this.offset + length));
+    if (value.length !== length) {
+      throw new Error('Account data too short');
+    }
+    this.offset += length;
+    return value;
+  }
+
+  pubkey(): PublicKey {
+    return new PublicKey(this.bytes(32));
+  }
+
+  string(): string {
+    return this.bytes(this.u32()).toString('utf8');
+  }
+
+  option<T>(read: () => T): T | null {
+    return this.bool() ? read() : null;
+  }
+
+  vec<T>(read: () => T): T[] {
+    const length = this.u32();
+    const items: T[] = [];
+    for (let i = 0; i < length; i++) {
+      items.push(read());
+    }
+    return items;
+  }
+}
+
+/** Checks the header and returns a reader positioned after it. */
+function readHeader(
+  data: Buffer,
+  discriminator: Buffer,
+  version: number,
+  name: string
+): AccountReader {
+  if (data.length <= DISCRIMINATOR_LEN || !data.subarray(0, DISCRIMINATOR_LEN).equals(discriminator)) {
+    throw new Error(`Account is not a ${name}`);
+  }
+  if (data[DISCRIMINATOR_LEN] !== version) {
+    throw new Error(`Unsupported ${name} layout version ${data[DISCRIMINATOR_LEN]}`);
+  }
+  return new AccountReader(data, DISCRIMINATOR_LEN + 1);
+}
+
+function readScope(reader: AccountReader): CredentialScope {
+  return {
+    allowedCommands: reader.u16(),
+    canDelegate: reader.bool(),
+    maxDelegationDepth: reader.u8(),
+    parameterBounds: reader.vec(() => ({
+      commandType: reader.u8() as CommandType,
+      offset: reader.u16(),
+      kind: reader.u8() as ParameterKind,
+      min: reader.i64(),
+      max: reader.i64(),
+    })),
+    timeWindows: reader.vec(() => ({
+      startMinute: reader.u16(),
+      endMinute: reader.u16(),
+    })),
+  };
+}
 
 class RobotAccount {
-  isInitialized: boolean;
-  owner: Uint8Array;
-  authority: Uint8Array;
-  status: number;
-  robotId: Uint8Array;
-  merkleRoot: Uint8Array;
-  lastCommandTimestamp: bigint;
-  totalCommandsExecuted: bigint;
-  activeOperators: Uint8Array[];
-  maxOperators: number;
-  emergencyStop: boolean;
-  metadataUri: string;
-
-  constructor(fields: any) {
-    this.isInitialized = fields.isInitialized;
-    this.owner = fields.owner;
-    this.authority = fields.authority;
-    this.status = fields.status;
-    this.robotId = fields.robotId;
-    this.merkleRoot = fields.merkleRoot;
-    this.lastCommandTimestamp = fields.lastCommandTimestamp;
-    this.totalCommandsExecuted = fields.totalCommandsExecuted;
-    this.activeOperators = fields.activeOperators;
-    this.maxOperators = fields.maxOperators;
-    this.emergencyStop = fields.emergencyStop;
-    this.metadataUri = fields.metadataUri;
-  }
-
-  static schema = new Map([
-    [
-      RobotAccount,
-      {
-        kind: 'struct',
-        fields: [
-          ['isInitialized', 'u8'],
-          ['owner', [32]],
-          ['authority', [32]],
-          ['status', 'u8'],
-          ['robotId', [32]],
-          ['merkleRoot', [32]],
-          ['lastCommandTimestamp', 'i64'],
-          ['totalCommandsExecuted', 'u64'],
-          ['activeOperators', [[32]]],
-          ['maxOperators', 'u8'],
-          ['emergencyStop', 'u8'],
-          ['metadataUri', 'string'],
-        ],
-      },
-    ],
-  ]);
+  static DISCRIMINATOR = Buffer.from('robot\0\0\0');
+  static VERSION = 1;
 
   static decode(data: Buffer): RobotData {
-    const decoded = deserialize(this.schema, RobotAccount, data) as RobotAccount;
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'robot');
     return {
-      isInitialized: decoded.isInitialized !== 0,
-      owner: new PublicKey(decoded.owner),
-      authority: new PublicKey(decoded.authority),
-      status: decoded.status as RobotStatus,
-      robotId: Buffer.from(decoded.robotId),
-      merkleRoot: Buffer.from(decoded.merkleRoot),
-      lastCommandTimestamp: Number(decoded.lastCommandTimestamp),
-      totalCommandsExecuted: Number(decoded.totalCommandsExecuted),
-      activeOperators: decoded.activeOperators.map((op) => new PublicKey(op)),
-      maxOperators: decoded.maxOperators,
-      emergencyStop: decoded.emergencyStop !== 0,
-      metadataUri: decoded.metadataUri,
+      version: this.VERSION,
+      owner: reader.pubkey(),
+      authority: reader.pubkey(),
+      pendingOwner: reader.option(() => reader.pubkey()),
+      pendingAuthority: reader.option(() => reader.pubkey()),
+      deviceKey: reader.pubkey(),
+      status: reader.u8() as RobotStatus,
+      robotId: reader.bytes(32),
+      merkleRoot: reader.bytes(32),
+      treeScheme: reader.u8() as MerkleTreeScheme,
+      revocationRoot: reader.bytes(32),
+      recentRoots: reader.vec(
+        (): RetiredRoot => ({
+          root: reader.bytes(32),
+          treeScheme: reader.u8() as MerkleTreeScheme,
+          retiredSlot: reader.u64(),
+          retiredAt: reader.i64(),
+        })
+      ),
+      rootGracePeriod: reader.i64(),
+      lastCommandTimestamp: reader.i64(),
+      totalCommandsExecuted: reader.u64(),
+      commandSequence: reader.u64(),
+      lastHeartbeat: reader.i64(),
+      batteryLevel: reader.option(() => reader.u8()),
+      healthFlags: reader.option(() => reader.u32()),
+      livenessTimeout: reader.i64(),
+      activeOperators: reader.vec(() => reader.pubkey()),
+      maxOperators: reader.u8(),
+      operatorPolicy: reader.u8() as OperatorPolicy,
+      commandPermissions: [...reader.bytes(COMMAND_TYPE_COUNT)] as PermissionLevel[],
+      guardians: reader.vec(() => reader.pubkey()),
+      emergencyStop: reader.bool(),
+      emergencyStopReason: reader.u16(),
+      emergencyStoppedBy: reader.pubkey(),
+      emergencyStoppedAt: reader.i64(),
+      resumeThreshold: reader.u8(),
+      resumeProposalTtl: reader.i64(),
+      proposalApprovers: reader.vec(() => reader.pubkey()),
+      proposalThreshold: reader.u8(),
+      proposalTimelock: reader.i64(),
+      proposalCount: reader.u64(),
+      openCredentials: reader.u32(),
+      openProposals: reader.u32(),
+      metadataUri: reader.string(),
     };
   }
 }
 
 class CredentialAccount {
-  isInitialized: boolean;
-  owner: Uint8Array;
-  robot: Uint8Array;
-  permissionLevel: number;
-  validFrom: bigint;
-  validUntil: bigint;
-  revoked: boolean;
-  credentialHash: Uint8Array;
-  issuer: Uint8Array;
-
-  constructor(fields: any) {
-    this.isInitialized = fields.isInitialized;
-    this.owner = fields.owner;
-    this.robot = fields.robot;
-    this.permissionLevel = fields.permissionLevel;
-    this.validFrom = fields.validFrom;
-    this.validUntil = fields.validUntil;
-    this.revoked = fields.revoked;
-    this.credentialHash = fields.credentialHash;
-    this.issuer = fields.issuer;
-  }
-
-  static schema = new Map([
-    [
-      CredentialAccount,
-      {
-        kind: 'struct',
-        fields: [
-          ['isInitialized', 'u8'],
-          ['owner', [32]],
-          ['robot', [32]],
-          ['permissionLevel', 'u8'],
-          ['validFrom', 'i64'],
-          ['validUntil', 'i64'],
-          ['revoked', 'u8'],
-          ['credentialHash', [32]],
-          ['issuer', [32]],
-        ],
-      },
-    ],
-  ]);
+  static DISCRIMINATOR = Buffer.from('credentl');
+  static VERSION = 1;
 
   static decode(data: Buffer): CredentialData {
-    const decoded = deserialize(this.schema, CredentialAccount, data) as CredentialAccount;
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'credential');
     return {
-      isInitialized: decoded.isInitialized !== 0,
-      owner: new PublicKey(decoded.owner),
-      robot: new PublicKey(decoded.robot),
-      permissionLevel: decoded.permissionLevel as PermissionLevel,
-      validFrom: Number(decoded.validFrom),
-      validUntil: Number(decoded.validUntil),
-      revoked: decoded.revoked !== 0,
-      credentialHash: Buffer.from(decoded.credentialHash),
-      issuer: new PublicKey(decoded.issuer),
+      version: this.VERSION,
+      owner: reader.pubkey(),
+      robot: reader.pubkey(),
+      permissionLevel: reader.u8() as PermissionLevel,
+      validFrom: reader.i64(),
+      validUntil: reader.i64(),
+      revoked: reader.bool(),
+      credentialHash: reader.bytes(32),
+      issuer: reader.pubkey(),
+      payer: reader.pubkey(),
+      parentCredential: reader.option(() => reader.pubkey()),
+      delegationDepth: reader.u8(),
+      scope: readScope(reader),
     };
   }
 }
 
 class CommandLogAccount {
-  isInitialized: boolean;
-  robot: Uint8Array;
-  executor: Uint8Array;
-  commandType: number;
-  timestamp: bigint;
-  parameters: Uint8Array;
-  success: boolean;
-  errorCode: number;
-
-  constructor(fields: any) {
-    this.isInitialized = fields.isInitialized;
-    this.robot = fields.robot;
-    this.executor = fields.executor;
-    this.commandType = fields.commandType;
-    this.timestamp = fields.timestamp;
-    this.parameters = fields.parameters;
-    this.success = fields.success;
-    this.errorCode = fields.errorCode;
-  }
-
-  static schema = new Map([
-    [
-      CommandLogAccount,
-      {
-        kind: 'struct',
-        fields: [
-          ['isInitialized', 'u8'],
-          ['robot', [32]],
-          ['executor', [32]],
-          ['commandType', 'u8'],
-          ['timestamp', 'i64'],
-          ['parameters', ['u8']],
-          ['success', 'u8'],
-          ['errorCode', 'u32'],
-        ],
-      },
-    ],
-  ]);
+  static DISCRIMINATOR = Buffer.from('cmdlog\0\0');
+  static VERSION = 1;
+  /** Size of the account `ExecuteCommand` expects the client to create. */
+  static LEN = DISCRIMINATOR_LEN + 1 + 32 + 32 + 1 + 8 + 8 + (4 + 256) + 1 + 1 + 4;
 
   static decode(data: Buffer): CommandLogData {
-    const decoded = deserialize(this.schema, CommandLogAccount, data) as CommandLogAccount;
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'command log');
     return {
-      isInitialized: decoded.isInitialized !== 0,
-      robot: new PublicKey(decoded.robot),
-      executor: new PublicKey(decoded.executor),
-      commandType: decoded.commandType as CommandType,
-      timestamp: Number(decoded.timestamp),
-      parameters: Buffer.from(decoded.parameters),
-      success: decoded.success !== 0,
-      errorCode: decoded.errorCode,
+      version: this.VERSION,
+      robot: reader.pubkey(),
+      executor: reader.pubkey(),
+      commandType: reader.u8() as CommandType,
+      timestamp: reader.i64(),
+      sequence: reader.u64(),
+      parameters: reader.bytes(reader.u32()),
+      status: reader.u8() as CommandStatus,
+      success: reader.bool(),
+      errorCode: reader.u32(),
     };
   }
 }
 
-export { RobotAccount, CredentialAccount, CommandLogAccount };
-
-
-
-
-
+class CredentialTreeAccount {
+  static DISCRIMINATOR = Buffer.from('credtree');
+  static VERSION = 1;
 
+  static decode(data: Buffer): CredentialTreeData {
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'credential tree');
+    const robot = reader.pubkey();
+    const depth = reader.u8();
+    const nextIndex = reader.u64();
+    reader.vec(() => reader.bytes(32));
+    const roots = reader.vec(() => reader.bytes(32));
+    const currentRootIndex = reader.u8();
+    return {
+      version: this.VERSION,
+      robot,
+      depth,
+      nextIndex,
+      currentRoot: roots[currentRootIndex],
+      roots,
+    };
+  }
+}
 
+class CommandQueueAccount {
+  static DISCRIMINATOR = Buffer.from('cmdqueue');
+  static VERSION = 1;
 
+  static decode(data: Buffer): CommandQueueData {
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'command queue');
+    return {
+      version: this.VERSION,
+      robot: reader.pubkey(),
+      entries: reader.vec(() =>
+        reader.option(
+          (): QueuedCommand => ({
+            sequence: reader.u64(),
+            commandLog: reader.pubkey(),
+            status: reader.u8() as CommandStatus,
+            enqueuedAt: reader.i64(),
+            updatedAt: reader.i64(),
+          })
+        )
+      ),
+    };
+  }
+}
 
+class ResumeProposalAccount {
+  static DISCRIMINATOR = Buffer.from('resumepr');
+  static VERSION = 1;
 
+  static decode(data: Buffer): ResumeProposalData {
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'resume proposal');
+    return {
+      version: this.VERSION,
+      robot: reader.pubkey(),
+      proposer: reader.pubkey(),
+      payer: reader.pubkey(),
+      createdAt: reader.i64(),
+      expiresAt: reader.i64(),
+      approvals: reader.vec(() => reader.pubkey()),
+    };
+  }
+}
 
+class MultisigAccount {
+  static DISCRIMINATOR = Buffer.from('multisig');
+  static VERSION = 1;
 
+  static decode(data: Buffer): MultisigData {
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'multisig');
+    return {
+      version: this.VERSION,
+      threshold: reader.u8(),
+      signers: reader.vec(() => reader.pubkey()),
+    };
+  }
+}
 
+class ProposalAccount {
+  static DISCRIMINATOR = Buffer.from('proposal');
+  static VERSION = 1;
 
+  static decode(data: Buffer): ProposalData {
+    const reader = readHeader(data, this.DISCRIMINATOR, this.VERSION, 'proposal');
+    return {
+      version: this.VERSION,
+      robot: reader.pubkey(),
+      proposer: reader.pubkey(),
+      payer: reader.pubkey(),
+      id: reader.u64(),
+      instructionData: reader.bytes(reader.u32()),
+      approvals: reader.vec(() => reader.pubkey()),
+      createdAt: reader.i64(),
+      approvedAt: reader.i64(),
+    };
+  }
+}
 
+export {
+  RobotAccount,
+  CredentialAccount,
+  CommandLogAccount,
+  CredentialTreeAccount,
+  CommandQueueAccount,
+  ResumeProposalAccount,
+  MultisigAccount,
+  ProposalAccount,
+};
@@ -29,23 +29,173 @@ export enum CommandType {
   Custom = 8,
 }
 
+export const COMMAND_TYPE_COUNT = 9;
+export const ALL_COMMANDS = (1 << COMMAND_TYPE_COUNT) - 1;
+
+export enum CommandStatus {
+  Queued = 0,
+  Started = 1,
+  Completed = 2,
+  Failed = 3,
+}
+
+export enum MerkleTreeScheme {
+  SortedPair = 0,
+  Positional = 1,
+}
+
+export enum OperatorPolicy {
+  CredentialOnly = 0,
+  OperatorListOnly = 1,
+  CredentialAndOperatorList = 2,
+}
+
+export enum AccountType {
+  Robot = 0,
+  Credential = 1,
+  CommandLog = 2,
+}
+
+export enum ParameterKind {
+  U8 = 0,
+  I8 = 1,
+  U16 = 2,
+  I16 = 3,
+  U32 = 4,
+  I32 = 5,
+  I64 = 6,
+}
+
+export interface ParameterBound {
+  commandType: CommandType;
+  offset: number;
+  kind: ParameterKind;
+  min: number;
+  max: number;
+}
+
+export interface TimeWindow {
+  startMinute: number;
+  endMinute: number;
+}
+
+export interface CredentialScope {
+  allowedCommands: number;
+  canDelegate: boolean;
+  maxDelegationDepth: number;
+  parameterBounds: ParameterBound[];
+  timeWindows: TimeWindow[];
+}
+
+export const DEFAULT_CREDENTIAL_SCOPE: CredentialScope = {
+  allowedCommands: ALL_COMMANDS,
+  canDelegate: false,
+  maxDelegationDepth: 0,
+  parameterBounds: [],
+  timeWindows: [],
+};
+
+export interface SparseMerkleProof {
+  siblingBitmap: Buffer;
+  siblings: Buffer[];
+}
+
+export const EMPTY_SPARSE_PROOF: SparseMerkleProof = {
+  siblingBitmap: Buffer.alloc(32),
+  siblings: [],
+};
+
+export interface CredentialProof {
+  merkleProof: Buffer[];
+  leafIndex: number;
+  revocationProof: SparseMerkleProof;
+}
+
+/** Batched sorted-pair proof for `VerifyCredentials`. */
+export interface MerkleMultiProof {
+  proof: Buffer[];
+  proofFlags: boolean[];
+}
+
+/** Batched positional proof for `VerifyCredentials`. */
+export interface PositionalMultiProof {
+  depth: number;
+  indices: number[];
+  proof: Buffer[];
+}
+
+export type CredentialMultiProof =
+  | { scheme: MerkleTreeScheme.SortedPair; proof: MerkleMultiProof }
+  | { scheme: MerkleTreeScheme.Positional; proof: PositionalMultiProof };
+
+export interface SparseMerkleMultiProof {
+  siblingSlots: number[];
+  siblings: Buffer[];
+}
+
+/**
+ * How an administrative instruction is authorized. By default the
+ * authority key signs itself. A multisig authority is passed unsigned and
+ * followed by its signing members; with `adminProof` the signer instead
+ * acts through an Administrator credential, passed with its parents
+ * (nearest first) and optionally the robot's credential tree.
+ */
+export interface Authorization {
+  multisigSigners?: PublicKey[];
+  adminProof?: CredentialProof;
+  adminCredentials?: PublicKey[];
+}
+
+export interface RetiredRoot {
+  root: Buffer;
+  treeScheme: MerkleTreeScheme;
+  retiredSlot: number;
+  retiredAt: number;
+}
+
 export interface RobotData {
-  isInitialized: boolean;
+  version: number;
   owner: PublicKey;
   authority: PublicKey;
+  pendingOwner: PublicKey | null;
+  pendingAuthority: PublicKey | null;
+  deviceKey: PublicKey;
   status: RobotStatus;
   robotId: Buffer;
   merkleRoot: Buffer;
+  treeScheme: MerkleTreeScheme;
+  revocationRoot: Buffer;
+  recentRoots: RetiredRoot[];
+  rootGracePeriod: number;
   lastCommandTimestamp: number;
   totalCommandsExecuted: number;
+  commandSequence: number;
+  lastHeartbeat: number;
+  batteryLevel: number | null;
+  healthFlags: number | null;
+  livenessTimeout: number;
   activeOperators: PublicKey[];
   maxOperators: number;
+  operatorPolicy: OperatorPolicy;
+  commandPermissions: PermissionLevel[];
+  guardians: PublicKey[];
   emergencyStop: boolean;
+  emergencyStopReason: number;
+  emergencyStoppedBy: PublicKey;
+  emergencyStoppedAt: number;
+  resumeThreshold: number;
+  resumeProposalTtl: number;
+  proposalApprovers: PublicKey[];
+  proposalThreshold: number;
+  proposalTimelock: number;
+  proposalCount: number;
+  openCredentials: number;
+  openProposals: number;
   metadataUri: string;
 }
 
 export interface CredentialData {
-  isInitialized: boolean;
+  version: number;
   owner: PublicKey;
   robot: PublicKey;
   permissionLevel: PermissionLevel;
@@ -54,25 +204,85 @@ export interface CredentialData {
   revoked: boolean;
   credentialHash: Buffer;
   issuer: PublicKey;
+  payer: PublicKey;
+  parentCredential: PublicKey | null;
+  delegationDepth: number;
+  scope: CredentialScope;
 }
 
 export interface CommandLogData {
-  isInitialized: boolean;
+  version: number;
   robot: PublicKey;
   executor: PublicKey;
   commandType: CommandType;
   timestamp: number;
+  sequence: number;
   parameters: Buffer;
+  status: CommandStatus;
   success: boolean;
   errorCode: number;
 }
 
+/** On-chain positional credential tree; only its roots are decoded. */
+export interface CredentialTreeData {
+  version: number;
+  robot: PublicKey;
+  depth: number;
+  nextIndex: number;
+  currentRoot: Buffer;
+  roots: Buffer[];
+}
+
+export interface QueuedCommand {
+  sequence: number;
+  commandLog: PublicKey;
+  status: CommandStatus;
+  enqueuedAt: number;
+  updatedAt: number;
+}
+
+export interface CommandQueueData {
+  version: number;
+  robot: PublicKey;
+  entries: (QueuedCommand | null)[];
+}
+
+export interface ResumeProposalData {
+  version: number;
+  robot: PublicKey;
+  proposer: PublicKey;
+  payer: PublicKey;
+  createdAt: number;
+  expiresAt: number;
+  approvals: PublicKey[];
+}
+
+export interface MultisigData {
+  version: number;
+  threshold: number;
+  signers: PublicKey[];
+}
+
+export interface ProposalData {
+  version: number;
+  robot: PublicKey;
+  proposer: PublicKey;
+  payer: PublicKey;
+  id: number;
+  instructionData: Buffer;
+  approvals: PublicKey[];
+  createdAt: number;
+  approvedAt: number;
+}
+
 export interface InitializeRobotParams {
   robotId: Buffer;
   merkleRoot: Buffer;
+  treeScheme?: MerkleTreeScheme;
   metadataUri: string;
   owner: PublicKey;
   authority: PublicKey;
+  deviceKey: PublicKey;
 }
 
 export interface IssueCredentialParams {
@@ -83,6 +293,23 @@ export interface IssueCredentialParams {
   validUntil: number;
   credentialHash: Buffer;
   issuer: PublicKey;
+  /** Pays the credential's rent; defaults to the issuer. */
+  payer?: PublicKey;
+  scope?: CredentialScope;
+  /** Appends the credential's leaf to the robot's on-chain credential tree. */
+  appendToTree?: boolean;
+}
+
+export interface DelegateCredentialParams {
+  recipient: PublicKey;
+  robot: PublicKey;
+  delegator: PublicKey;
+  parentCredential: PublicKey;
+  permissionLevel: PermissionLevel;
+  validFrom: number;
+  validUntil: number;
+  credentialHash: Buffer;
+  scope: CredentialScope;
 }
 
 export interface ExecuteCommandParams {
@@ -91,26 +318,11 @@ export interface ExecuteCommandParams {
   credential: PublicKey;
   commandType: CommandType;
   parameters: Buffer;
-  merkleProof: Buffer[];
+  proof: CredentialProof;
+  /** The robot's current `commandSequence`. */
+  expectedSequence: number;
+  /** Parent credentials of a delegated credential, nearest first. */
+  parentCredentials?: PublicKey[];
+  /** Prove against the on-chain credential tree's recent roots. */
+  credentialTree?: PublicKey;
 }
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
@@ -1,5 +1,6 @@
-import { PublicKey, Keypair } from '@solana/web3.js';
+import { PublicKey } from '@solana/web3.js';
 import { createHash, randomBytes } from 'crypto';
+import { CommandType, PermissionLevel, RobotData } from './types';
 
 export function generateRobotId(): Buffer {
   return randomBytes(32);
@@ -19,10 +20,65 @@ export function deriveRobotAddress(
 export function deriveCredentialAddress(
   programId: PublicKey,
   robot: PublicKey,
-  owner: PublicKey
+  recipient: PublicKey,
+  credentialHash: Buffer
 ): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
-    [Buffer.from('credential'), robot.toBuffer(), owner.toBuffer()],
+    [Buffer.from('credential'), robot.toBuffer(), recipient.toBuffer(), credentialHash],
+    programId
+  );
+}
+
+export function deriveCredentialTreeAddress(
+  programId: PublicKey,
+  robot: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('credential_tree'), robot.toBuffer()],
+    programId
+  );
+}
+
+export function deriveCommandQueueAddress(
+  programId: PublicKey,
+  robot: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('command_queue'), robot.toBuffer()],
+    programId
+  );
+}
+
+export function deriveResumeProposalAddress(
+  programId: PublicKey,
+  robot: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('resume_proposal'), robot.toBuffer()],
+    programId
+  );
+}
+
+export function deriveMultisigAddress(
+  programId: PublicKey,
+  creator: PublicKey,
+  multisigId: Buffer
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('multisig'), creator.toBuffer(), multisigId],
+    programId
+  );
+}
+
+export function deriveProposalAddress(
+  programId: PublicKey,
+  robot: PublicKey,
+  id: number
+): [PublicKey, number] {
+  const idBuffer = Buffer.alloc(8);
+  idBuffer.writeBigUInt64LE(BigInt(id), 0);
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('proposal'), robot.toBuffer(), idBuffer],
     programId
   );
 }
@@ -49,18 +105,14 @@ export function isValidPermissionLevel(level: number): boolean {
 }
 
 export function canExecuteCommand(
-  permissionLevel: number,
-  commandType: number
+  robot: RobotData,
+  permissionLevel: PermissionLevel,
+  commandType: CommandType
 ): boolean {
-  if (permissionLevel === 0 || permissionLevel === 1) {
-    return false;
-  }
-  
-  if (commandType === 4 || commandType === 7) {
-    return permissionLevel >= 3;
-  }
-  
-  return true;
+  return (
+    permissionLevel >= PermissionLevel.Operator &&
+    permissionLevel >= robot.commandPermissions[commandType]
+  );
 }
 
 export class RateLimiter {